@@ -0,0 +1,39 @@
+use std::{env, fs, process::Command};
+
+// Capture the git hash of the firmware build, reported by the handshake,
+// the device address, `DEV_ID=2 cargo embed ...`, and with the `auth`
+// feature the pre-shared key, `AUTH_KEY_FILE=../auth.key cargo embed ...`
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short=8", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+
+    let dev_id = env::var("DEV_ID").unwrap_or_else(|_| "1".to_string());
+    println!("cargo:rustc-env=DEV_ID={}", dev_id);
+    println!("cargo:rerun-if-env-changed=DEV_ID");
+
+    println!("cargo:rerun-if-env-changed=AUTH_KEY");
+    println!("cargo:rerun-if-env-changed=AUTH_KEY_FILE");
+    if env::var_os("CARGO_FEATURE_AUTH").is_some() {
+        // checked in the firmware by `shared::auth::parse_key`
+        let key = match (env::var("AUTH_KEY"), env::var("AUTH_KEY_FILE")) {
+            (Ok(key), _) => key,
+            (_, Ok(path)) => {
+                println!("cargo:rerun-if-changed={}", path);
+                fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("cannot read AUTH_KEY_FILE {}: {}", path, e))
+            }
+            _ => panic!("the auth feature needs AUTH_KEY or AUTH_KEY_FILE"),
+        };
+        println!("cargo:rustc-env=AUTH_KEY={}", key.trim());
+    }
+}
@@ -50,6 +50,7 @@ mod app {
     }
 
     use rtic_sync::{channel::*, make_channel};
+    use rtic::mutex_prelude::*;
     use rtt_target::{rprintln, rtt_init_print};
 
     use smart_leds::{
@@ -61,23 +62,75 @@ mod app {
     use esp_hal_smartled::{smartLedAdapter, SmartLedsAdapter};
 
 
-    use core::mem::size_of;
-
-    use chrono::prelude::*;
-
-    use chrono::{Utc};
 
     // shared libs
-    use corncobs::{max_encoded_len, ZERO};
-    use shared::{deserialize_crc_cobs, serialize_crc_cobs, Command, Message, Response, Faults}; // local library
+    use shared::{frame_accumulator::FrameAccumulator, deserialize_crc_cobs, encoder::{serialize_crc_cobs_to, EncodeError}, Command, Action, Response, Faults, Seq, DevId, Id, Parameter, get, Upstream}; // local library
+    use shared::error::{self, ErrorCode};
+    use shared::address;
+    #[cfg(feature = "auth")]
+    use shared::auth;
+    use shared::batch::{self, Batch};
+    use shared::dedup::{self, ResponseCache};
+    use shared::event::Event;
+    use shared::wire;
+    use shared::date_time::{self, UtcDateTime};
+    use shared::time_zone::TimeZone;
+    use shared::encoded_size::{max_frame_len, max_payload_len};
+    use shared::hello::{self, HelloInfo, PROTOCOL_VERSION};
+
+    const IN_SIZE: usize = max_frame_len::<Command>();
+    // serialized response before encoding, frames are COBS-encoded straight into the UART
+    const OUT_SIZE: usize = max_payload_len::<Upstream>();
+
+    type RxFrames = FrameAccumulator<IN_SIZE>;
+    type OutScratch = [u8; OUT_SIZE];
+
+    // with `auth` only frames signed with the key from build.rs are accepted
+    #[cfg(feature = "auth")]
+    type FrameVerifier = auth::Verifier;
+    #[cfg(not(feature = "auth"))]
+    type FrameVerifier = ();
+
+    #[cfg(feature = "auth")]
+    const AUTH_KEY: auth::Key = match auth::parse_key(env!("AUTH_KEY")) {
+        Some(key) => key,
+        None => panic!("AUTH_KEY is not a valid key"),
+    };
 
-    const IN_SIZE: usize = max_encoded_len(size_of::<Command>() + size_of::<u32>());
-    const OUT_SIZE: usize = max_encoded_len(size_of::<Response>() + size_of::<u32>());
+    const CAPACITY: usize = 100;
 
-    type InBuf = [u8; IN_SIZE];
-    type OutBuf = [u8; OUT_SIZE];
+    // address on the bus, `DEV_ID=2 cargo embed ...`, see build.rs
+    const DEV_ID: DevId = match address::parse(env!("DEV_ID")) {
+        Some(dev_id) => dev_id,
+        None => panic!("DEV_ID is not a valid device address"),
+    };
 
-    const CAPACITY: usize = 100;
+    // on a shared bus every device would answer an unreadable frame at once
+    const REPLY_TO_FAULTS: bool = !cfg!(feature = "multi-drop");
+    // nor can devices on a shared bus speak unasked
+    const EVENTS: bool = !cfg!(feature = "multi-drop");
+
+    // everything this firmware knows how to handle, reported in the handshake
+    const CAPABILITIES: hello::Capabilities = hello::CAP_SET_TIME
+        | hello::CAP_BLINK_OFF
+        | hello::CAP_BLINK_NOW
+        | hello::CAP_BLINK_AT
+        | hello::CAP_SET_RGB
+        | hello::CAP_GET
+        | hello::CAP_HELLO
+        | hello::CAP_ACTION
+        | hello::CAP_BATCH
+        | hello::CAP_EPOCH_TIME
+        | hello::CAP_TIME_ZONE
+        | if EVENTS { hello::CAP_EVENTS } else { 0 };
+
+    const HELLO_INFO: HelloInfo = HelloInfo {
+        protocol_version: PROTOCOL_VERSION,
+        fw_version: hello::padded(env!("CARGO_PKG_VERSION")),
+        git_hash: hello::padded(env!("GIT_HASH")),
+        dev_id: DEV_ID,
+        capabilities: CAPABILITIES,
+    };
 
     #[shared]
     struct Shared {
@@ -86,6 +139,8 @@ mod app {
       tg0_timer0 : Timer<Timer0<TIMG0>>,
       blink_led: Gpio7<Output<PushPull>>,
       color_led_active : bool,
+      uptime_millis : u64,
+      time_zone : TimeZone,
     }
 
     #[local]
@@ -96,17 +151,25 @@ mod app {
         rtc : Rtc<'static>,
         tx: UartTx<'static, UART0>,
         rx: UartRx<'static, UART0>,
-        sender: Sender<'static, Response, CAPACITY>,
-        rx_buff: InBuf,
-        rx_idx: usize,
+        sender: Sender<'static, (Seq, Upstream), CAPACITY>,
+        event_sender: Sender<'static, (Seq, Upstream), CAPACITY>,
+        rx_frames: RxFrames,
         time_set: bool,
+        responses: ResponseCache,
+        verifier: FrameVerifier,
     }
     
     #[init]
     fn init(_: init::Context) -> (Shared, Local) {
         rtt_init_print!();
         rprintln!("uart_echo_split");
-        let (sender, receiver) = make_channel!(Response, CAPACITY);
+        rprintln!("wire format: {}", wire::BACKEND);
+        rprintln!("authenticated frames: {}", cfg!(feature = "auth"));
+        let (sender, receiver) = make_channel!((Seq, Upstream), CAPACITY);
+        let mut event_sender = sender.clone();
+        // the device starts without a reference time
+        send_event(&mut event_sender, Event::Rebooted);
+        send_event(&mut event_sender, Event::TimeNotSet);
 
         let peripherals = Peripherals::take();
         let mut system = peripherals.SYSTEM.split();
@@ -158,14 +221,13 @@ mod app {
 
         let (tx, rx) = uart0.split();
 
-        let rx_buff : InBuf = [0; IN_SIZE];
-        let rx_idx  : usize = 0;
+        let rx_frames = RxFrames::new();
 
         let rtc = Rtc::new(peripherals.RTC_CNTL);
         
-        let dt = Utc.with_ymd_and_hms(2023, 1, 1,0, 0, 0).unwrap();
+        let dt = UtcDateTime { year: 2023, month: 1, day: 1, hour: 0, minute: 0, second: 0, nanoseconds: 0 };
 
-        let epoch_millis = dt.timestamp_millis();
+        let epoch_millis = dt.to_epoch_millis().unwrap();
 
         let previous_rtc_timestamp = rtc.get_time_ms();
 
@@ -178,6 +240,11 @@ mod app {
 
         let time_set : bool = false;
 
+        // response to the most recent command, replayed on retransmission
+        let responses = ResponseCache::new();
+
+        let verifier = frame_verifier();
+
         uart_tx::spawn(receiver).unwrap();
 
         let mut blink_led = io.pins.gpio7.into_push_pull_output();
@@ -196,6 +263,9 @@ mod app {
 
         let color_led: SmartLedsAdapter<esp32c3_hal::rmt::Channel0<0>, 0, 25> = <smartLedAdapter!(0, 1)>::new(rmt.channel0, io.pins.gpio2);
         let color_led_active = true;
+        let uptime_millis : u64 = 0;
+        // UTC until the host says otherwise
+        let time_zone = TimeZone::UTC;
         (
             Shared {
               epoch_millis,
@@ -203,6 +273,8 @@ mod app {
               tg0_timer0,
               blink_led,
               color_led_active,
+              uptime_millis,
+              time_zone,
             },
             Local {
               color_led,
@@ -212,9 +284,11 @@ mod app {
               tx,
               rx,
               sender,
-              rx_buff,
-              rx_idx,
+              event_sender,
+              rx_frames,
               time_set,
+              responses,
+              verifier,
             },
         )
     }
@@ -227,162 +301,89 @@ mod app {
         }
     }
 
-    #[task(binds = UART0, priority=2, local = [ rx, sender, rx_buff, rx_idx, time_set], shared = [epoch_millis, blink_led_config, color_led_active])]
+    #[task(binds = UART0, priority=2, local = [ rx, sender, rx_frames, time_set, responses, verifier], shared = [epoch_millis, blink_led_config, color_led_active, uptime_millis, time_zone])]
     fn uart0(mut cx: uart0::Context) {
         
         let rx = cx.local.rx;
         let sender = cx.local.sender;
         
-        let rx_buff = cx.local.rx_buff;
-        let rx_idx = cx.local.rx_idx;
-        
+        let rx_frames = cx.local.rx_frames;
+        let dropped_frames = rx_frames.dropped_frames();
+        let dropped_bytes = rx_frames.dropped_bytes();
 
         while let nb::Result::Ok(c) = rx.read() {
 
-            rx_buff[*rx_idx] = c;
-
-            // reset element idx counter when eof received
-            if c == ZERO {
-              
-              *rx_idx = 0;
+            // complete frame received when the ZERO delimiter arrives
+            if let Some(frame) = rx_frames.push(c) {
 
-              let cmd_res = deserialize_crc_cobs(rx_buff);
+              let cmd_res = decode_command(frame, cx.local.verifier);
               let mut rsp = Response::SetOk;
+              // responses to unreadable frames carry seq 0, the host cannot correlate them anyway
+              let mut rsp_seq : Seq = 0;
+              let mut cache_rsp : bool = false;
+
+              // frames for other devices on the bus are none of our business
+              if let Ok((_, cmd)) = &cmd_res {
+                if !cmd.addressed_to(DEV_ID) {
+                  continue;
+                }
+              }
+              // every device carries out a broadcast, their answers would collide
+              let mut reply = !matches!(&cmd_res, Ok((_, cmd)) if cmd.is_broadcast());
+              // a new command reusing the cached seq must not be mistaken for a retransmission
+              let digest = cmd_res.as_ref().map_or(0, |(_, cmd)| dedup::digest(cmd));
               
               match cmd_res {
 
+                // retransmitted command, replay the cached response rather than applying it twice
+                Ok((seq, _)) if cx.local.responses.replay(seq, digest).is_some() => {
+                  rprintln!("Received retransmission of seq {}, replaying response", seq);
+                  rsp_seq = seq;
+                  if let Some(cached) = cx.local.responses.replay(seq, digest) {
+                    rsp = cached.clone();
+                  }
+                },
+
                 // extract command if no errors were identified during the deserialise process
-                Ok(cmd) => {
+                Ok((seq, cmd)) => {
+
+                  rsp_seq = seq;
+                  cache_rsp = true;
 
                   match cmd {
 
                     Command::Set(id, msg, devid) => {
-                      
-                      // 
-                      match msg {
-
-                        Message::A(udt) => {
 
-                          if (id != 1) {
-                            
-                            rsp = Response::Illegal;
-                            
-                          } else {
-
-                              rprintln!("Received Set({}, [year={}, month={}, day={}, hour={}, min={}, sec={}, nsec={}],{})", id, udt.year, udt.month, udt.day, udt.hour, udt.minute, udt.second, udt.nanoseconds, devid);                        
-                  
-                              let dt = Utc.with_ymd_and_hms(udt.year, udt.month, udt.day, udt.hour, udt.minute, udt.second).unwrap();
-              
-                              let new_epoch_millis = dt.timestamp_millis();
-        
-                              cx.shared.epoch_millis.lock(|epoch_millis| {
-                                *epoch_millis = new_epoch_millis;
-                              });
-
-                              *cx.local.time_set = true;
-                          }
-                        },
-
-                        Message::B(int_val) => {
-
-                            if (id != 2 && id != 5) {
-
-                              rsp = Response::Illegal;
-
-                            } else {
-
-                                rprintln!("Received Set({},{},{})", id, int_val, devid);
-
-                                if id == 2 {
-                                    cx.shared.blink_led_config.lock(|config| {
-                                      // Set this to zero so we stop blinking
-                                      config.blink_end_time = 0;
-                                    });
-
-                                } else if id == 5 {
-                                  cx.shared.color_led_active.lock(|active| {
-                                      *active = int_val != 0;
-                                  });
-                                }
-                            }
+                      // legacy encoding, only some id/message pairings are meaningful
+                      match Action::try_from((id, msg)) {
+                        Ok(action) => {
+                          rsp = apply_action(action, devid, &mut cx.shared, cx.local.time_set);
                         },
-
-                        Message::C(duration_secs, freq_hz) => {
-
-                            if (id != 3) {
-                            
-                                rsp = Response::Illegal;
-
-                            } else {
-
-                                rprintln!("Received Set({},({} sec, {} Hz),{})", id, duration_secs, freq_hz, devid);
-
-                                let mut time_stamp = 0;
-                                // saturate frequency at 100Hz
-                                let sat_freq_hz = {
-                                  if freq_hz > 100 {
-                                    100
-                                  } else {
-                                    freq_hz
-                                  }
-                                };
-                              
-
-                                //Avoid nested locks
-                                cx.shared.epoch_millis.lock(|epoch_millis| {
-                                    time_stamp = *epoch_millis;
-                                });
-                              
-                                cx.shared.blink_led_config.lock(|config| {
-                                    config.blink_end_time = time_stamp + ((duration_secs as i64)*1000);
-                                    //TODO: this would act funny after 1 kHz
-                                    config.blink_period_millis = 1000/sat_freq_hz;
-                                });
-                            }
+                        Err((id, msg)) => {
+                          rprintln!("[ERROR] - Unknown Set({}, {:?}, {})", id, msg, devid);
+                          rsp = Response::Error(ErrorCode::UnknownCommand, id);
                         },
+                      };
+                    },
 
-                        Message::D(udt, duration_secs, freq_hz) => {
-                          
-                            if (id != 4 || *cx.local.time_set == false) {
-
-                                rsp = Response::Illegal;
-
-                            } else {
-
-                                rprintln!("Received Set({}, ([year={}, month={}, day={}, hour={}, min={}, sec={}, nsec={}], {} sec, {} Hz, {})", id, udt.year, udt.month, udt.day, udt.hour, udt.minute, udt.second, udt.nanoseconds, duration_secs, freq_hz, devid);
-                                let dt = Utc.with_ymd_and_hms(udt.year, udt.month, udt.day, udt.hour, udt.minute, udt.second).unwrap();
-                            
-                                let start_time = dt.timestamp_millis();
-                                // saturate frequency at 100Hz
-                                let sat_freq_hz = {
-                                  if freq_hz > 100 {
-                                    100
-                                  } else {
-                                    freq_hz
-                                  }
-
-                                };                        
-
-                                cx.shared.blink_led_config.lock(|config| {
-                                  config.blink_start_time = start_time;
-                                  config.blink_end_time = start_time + ((duration_secs as i64)*1000);
-                                  //TODO: this would act funny after 1 kHz
-                                  config.blink_period_millis = 1000/sat_freq_hz;
-                                });  
-                            }                    
-                        },
+                    Command::Action(action, devid) => {
+                        rsp = apply_action(action, devid, &mut cx.shared, cx.local.time_set);
+                    },
 
-                        _ => {
-                            rprintln!("[ERROR] - Set Message format not recognised!");
-                            rsp = Response::Illegal;
-                        },
-                      };
+                    Command::Batch(actions, devid) => {
+                        rprintln!("Received Batch of {} action(s), {}", actions.iter().flatten().count(), devid);
+                        rsp = apply_batch(actions, devid, &mut cx.shared, cx.local.time_set);
                     },
 
                     Command::Get(id, param, devid) => {
                         rprintln!("Received Get({},{},{})", id, param, devid);
+                        rsp = get_value(id, param, devid, &mut cx.shared, *cx.local.time_set);
                     },
 
+                    Command::Hello(devid) => {
+                        rprintln!("Received Hello({})", devid);
+                        rsp = Response::Hello(HELLO_INFO);
+                    },
                   };
                 },
                 // Use the error reported in the serialise process to determine how to respond
@@ -392,72 +393,289 @@ mod app {
     
                     Faults::BitFlipData => { 
                       rprintln!("Detected bitflip in payload or CRC!");
-                      rsp = Response::NotOK;
+                    },
+                    Faults::AuthFailed => {
+                      rprintln!("[ERROR] - Frame not signed with our key, or replayed");
                     },
                     _ => {
-                      rprintln!("[ERROR] - Received cmd not recognised!");
-                      rsp = Response::NotOK;
+                      rprintln!("[ERROR] - Received cmd not recognised: {:?}", fault);
                     },
                   };
+
+                  // CRC mismatch -> Error(CrcMismatch), bad MAC or counter -> Error(AuthFailed),
+                  // malformed frame -> ParseError
+                  rsp = fault.into();
+                  reply = REPLY_TO_FAULTS;
                 }
               };
 
-              match sender.try_send(rsp) {
-                Err(_) => {
-                    rprintln!("send buffer full");
+              if cache_rsp {
+                cx.local.responses.store(rsp_seq, digest, rsp.clone());
+              }
+
+              if reply {
+                match sender.try_send((rsp_seq, Upstream::Response(rsp))) {
+                  Err(_) => {
+                      rprintln!("send buffer full");
+                  }
+                  _ => {}
                 }
-                _ => {}
+              }
             }
-              
-            } else {
-              
-              *rx_idx = *rx_idx + 1;
+        }
+
+        if rx_frames.dropped_frames() != dropped_frames {
+            rprintln!("[ERROR] - Oversized frame dropped ({} bytes dropped in total)", rx_frames.dropped_bytes());
+            // unreadable, so like the other framing errors it carries seq 0
+            let dropped = rx_frames.dropped_bytes().wrapping_sub(dropped_bytes);
+            if REPLY_TO_FAULTS && sender.try_send((0, Upstream::Response(Response::Error(ErrorCode::BufferOverflow, dropped)))).is_err() {
+                rprintln!("send buffer full");
             }
+            send_event(sender, Event::RxOverflow(dropped));
         }
         //rprintln!("");
         rx.reset_rx_fifo_full_interrupt()
     }
 
+    // Apply a typed command, shared by the legacy `Set` and the `Action` encodings
+    fn apply_action(action: Action, devid: DevId, shared: &mut uart0::SharedResources, time_set: &mut bool) -> Response {
+
+        match apply_actions(&[Some(action)], devid, shared, time_set) {
+            Ok(()) => Response::SetOk,
+            Err((_, code, detail)) => Response::Error(code, detail),
+        }
+    }
+
+    fn apply_batch(actions: Batch, devid: DevId, shared: &mut uart0::SharedResources, time_set: &mut bool) -> Response {
+
+        match apply_actions(&actions, devid, shared, time_set) {
+            Ok(()) => Response::SetOk,
+            Err((index, code, detail)) => {
+                rprintln!("[ERROR] - Batch action {} rejected: {:?}({}), nothing applied", index, code, detail);
+                Response::BatchRejected(index, code, detail)
+            },
+        }
+    }
+
+    // Check every action first, then apply them all under one lock,
+    // so that advance_time never sees a half applied configuration
+    fn apply_actions(actions: &[Option<Action>], devid: DevId, shared: &mut uart0::SharedResources, time_set: &mut bool) -> Result<(), (u8, ErrorCode, error::Detail)> {
+
+        batch::validate(actions, *time_set)?;
+
+        (&mut shared.epoch_millis, &mut shared.blink_led_config, &mut shared.color_led_active, &mut shared.time_zone).lock(|epoch_millis, config, color_led_active, time_zone| {
+            for action in actions.iter().flatten() {
+                apply_checked(action, devid, epoch_millis, config, color_led_active, time_zone, time_set);
+            }
+        });
+
+        Ok(())
+    }
+
+    // Carry out an action that passed `error::check_action`
+    fn apply_checked(action: &Action, devid: DevId, epoch_millis: &mut i64, config: &mut BlinkLedConfig, color_led_active: &mut bool, time_zone: &mut TimeZone, time_set: &mut bool) {
+
+        match *action {
+
+          Action::SetTime(udt) => {
+
+              rprintln!("Received SetTime([year={}, month={}, day={}, hour={}, min={}, sec={}, nsec={}],{})", udt.year, udt.month, udt.day, udt.hour, udt.minute, udt.second, udt.nanoseconds, devid);
+
+              // checked, the date is valid
+              match udt.to_epoch_millis() {
+                  Ok(millis) => {
+                      *epoch_millis = millis;
+                      *time_set = true;
+                  },
+                  Err(e) => rprintln!("[ERROR] - SetTime: {}", e),
+              }
+          },
+
+          Action::BlinkOff => {
+
+              rprintln!("Received BlinkOff({})", devid);
+
+              // Set this to zero so we stop blinking
+              config.blink_end_time = 0;
+          },
+
+          Action::BlinkNow(duration_secs, freq_hz) => {
+
+              rprintln!("Received BlinkNow({} sec, {} Hz, {})", duration_secs, freq_hz, devid);
+
+              config.blink_end_time = *epoch_millis + ((duration_secs as i64)*1000);
+              // frequency is at most MAX_FREQ_HZ, so the period never rounds to zero
+              config.blink_period_millis = 1000/freq_hz;
+          },
+
+          Action::BlinkAt(udt, duration_secs, freq_hz) => {
+
+              rprintln!("Received BlinkAt([year={}, month={}, day={}, hour={}, min={}, sec={}, nsec={}], {} sec, {} Hz, {})", udt.year, udt.month, udt.day, udt.hour, udt.minute, udt.second, udt.nanoseconds, duration_secs, freq_hz, devid);
+
+              match udt.to_epoch_millis() {
+                  Ok(start_time) => {
+                      config.blink_start_time = start_time;
+                      config.blink_end_time = start_time + ((duration_secs as i64)*1000);
+                      config.blink_period_millis = 1000/freq_hz;
+                  },
+                  Err(e) => rprintln!("[ERROR] - BlinkAt: {}", e),
+              }
+          },
+
+          // the compact forms, nothing left to check or convert but the unit
+          Action::SetTimeEpoch(nanos) => {
+
+              rprintln!("Received SetTimeEpoch({} ns, {})", nanos, devid);
+
+              *epoch_millis = date_time::epoch_nanos_to_millis(nanos);
+              *time_set = true;
+          },
+
+          Action::BlinkAtEpoch(nanos, duration_secs, freq_hz) => {
+
+              rprintln!("Received BlinkAtEpoch({} ns, {} sec, {} Hz, {})", nanos, duration_secs, freq_hz, devid);
+
+              let start_time = date_time::epoch_nanos_to_millis(nanos);
+              config.blink_start_time = start_time;
+              config.blink_end_time = start_time + ((duration_secs as i64)*1000);
+              config.blink_period_millis = 1000/freq_hz;
+          },
+
+          Action::SetRgb(state) => {
+
+              rprintln!("Received SetRgb({}, {})", state, devid);
+
+              *color_led_active = state;
+          },
+
+          Action::SetTimeZone(tz) => {
+
+              rprintln!("Received SetTimeZone({:?}, {})", tz, devid);
+
+              // only changes what local time means, the clock itself stays in UTC
+              *time_zone = tz;
+          },
+        };
+    }
+
+    // Read back device state for `Command::Get`, see `shared::get` for the ids
+    fn get_value(id: Id, param: Parameter, devid: DevId, shared: &mut uart0::SharedResources, time_set: bool) -> Response {
+
+        let value : Option<i64> = match (id, param) {
+            (get::TIME, _) => Some(shared.epoch_millis.lock(|epoch_millis| *epoch_millis)),
+            (get::TIME_SET, _) => Some(time_set as i64),
+            (get::BLINK_CONFIG, get::BLINK_START) => Some(shared.blink_led_config.lock(|config| config.blink_start_time)),
+            (get::BLINK_CONFIG, get::BLINK_END) => Some(shared.blink_led_config.lock(|config| config.blink_end_time)),
+            (get::BLINK_CONFIG, get::BLINK_PERIOD) => Some(shared.blink_led_config.lock(|config| config.blink_period_millis as i64)),
+            (get::BLINK_CONFIG, get::BLINK_ACTIVE) => Some(shared.blink_led_config.lock(|config| config.active as i64)),
+            (get::RGB_ACTIVE, _) => Some(shared.color_led_active.lock(|active| *active as i64)),
+            (get::UPTIME, _) => Some(shared.uptime_millis.lock(|uptime| *uptime as i64)),
+            (get::UTC_OFFSET, _) => Some((&mut shared.time_zone, &mut shared.epoch_millis).lock(|tz, epoch_millis| tz.offset_minutes_at(*epoch_millis) as i64)),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Response::Data(id, param, value, devid),
+            None => {
+                rprintln!("[ERROR] - Unknown Get({},{})", id, param);
+                Response::Error(ErrorCode::UnknownCommand, id)
+            },
+        }
+    }
+
+    // Push an unsolicited event, seq 0 as it answers nothing
+    fn send_event(sender: &mut Sender<'static, (Seq, Upstream), CAPACITY>, event: Event) {
+        if EVENTS && sender.try_send((0, Upstream::Event(event, DEV_ID))).is_err() {
+            rprintln!("send buffer full, dropping {:?}", event);
+        }
+    }
+
+    #[cfg(feature = "auth")]
+    fn frame_verifier() -> FrameVerifier {
+        auth::Verifier::new(AUTH_KEY)
+    }
+
+    #[cfg(not(feature = "auth"))]
+    fn frame_verifier() -> FrameVerifier {}
+
+    // Check the MAC and counter of a command frame when built with `auth`
+    #[cfg(feature = "auth")]
+    fn decode_command(frame: &mut [u8], verifier: &mut FrameVerifier) -> Result<(Seq, Command), Faults> {
+        verifier.deserialize_crc_cobs(frame)
+    }
+
+    #[cfg(not(feature = "auth"))]
+    fn decode_command(frame: &mut [u8], _: &mut FrameVerifier) -> Result<(Seq, Command), Faults> {
+        deserialize_crc_cobs(frame)
+    }
+
     #[task(priority = 1, local = [ tx ])]
-    async fn uart_tx(cx: uart_tx::Context, mut receiver: Receiver<'static, Response, CAPACITY>) {
+    async fn uart_tx(cx: uart_tx::Context, mut receiver: Receiver<'static, (Seq, Upstream), CAPACITY>) {
         
         rprintln!("uart_tx started");
         let tx = cx.local.tx;
 
-        while let Ok(c) = receiver.recv().await {
+        while let Ok((seq, up)) = receiver.recv().await {
 
-            let mut tx_buff : OutBuf = [0; OUT_SIZE];
+            let mut scratch : OutScratch = [0; OUT_SIZE];
 
-            match c {
+            match &up {
 
-              Response::SetOk => {
-                rprintln!("Sending Response::SetOk");
+              Upstream::Event(event, devid) => {
+                rprintln!("Sending Event::{:?} from {}", event, devid);
               },
 
-              Response::ParseError => {
-                rprintln!("Sending Response::ParseError");
-              },
+              Upstream::Response(c) => match c {
 
-              Response::Data(id, param, val, devid) => {
-                rprintln!("Sending Response::Data({},{},{},{}", id, param, val, devid);
-              },
+                Response::SetOk => {
+                  rprintln!("Sending Response::SetOk");
+                },
 
-              Response::NotOK => {
-                rprintln!("Sending Response::NotOK");
-              },
+                Response::ParseError => {
+                  rprintln!("Sending Response::ParseError");
+                },
+
+                Response::Data(id, param, val, devid) => {
+                  rprintln!("Sending Response::Data({},{},{},{}", id, param, val, devid);
+                },
+
+                Response::NotOK => {
+                  rprintln!("Sending Response::NotOK");
+                },
               
-              Response::Illegal => {
-                rprintln!("Sending Response::Illegal");
+                Response::Illegal => {
+                  rprintln!("Sending Response::Illegal");
+                },
+
+                Response::Hello(info) => {
+                  rprintln!("Sending Response::Hello(v{}, {} {}, caps {:#b})", info.protocol_version, info.fw_version(), info.git_hash(), info.capabilities);
+                },
+
+                Response::Error(code, detail) => {
+                  rprintln!("Sending Response::Error({:?}, {}): {}", code, detail, code.description());
+                },
+
+                Response::BatchRejected(i, code, detail) => {
+                  rprintln!("Sending Response::BatchRejected({}, {:?}, {})", i, code, detail);
+                },
               },
             }
 
-            let to_write = serialize_crc_cobs(&c, &mut tx_buff, false);
-            tx.write_bytes(to_write).unwrap();
+            match serialize_crc_cobs_to(seq, &up, &mut scratch, |bytes: &[u8]| tx.write_bytes(bytes)) {
+              Ok(_) => {},
+              Err(EncodeError::Fault(fault)) => {
+                rprintln!("[ERROR] - Could not encode response: {:?}", fault);
+              },
+              Err(EncodeError::Sink(err)) => {
+                rprintln!("[ERROR] - UART write failed: {:?}", err);
+              },
+            }
         }
     }
 
-    fn get_led_color(epoch_millis : i64) -> RGB<u8> {
-        let hours = Utc.timestamp_opt(epoch_millis/1000, 0).unwrap().hour();
+    // `local_millis` is wall-clock time, so the colours follow the local hours
+    fn get_led_color(local_millis : i64) -> RGB<u8> {
+        let hours = UtcDateTime::from_epoch_millis(local_millis).map_or(0, |dt| dt.hour);
         if hours >= 3 && hours < 9 {
             return RGB {r: 0xF8, g: 0xF3, b: 0x2B};
         } else if hours >= 9 && hours < 15 {
@@ -486,8 +704,8 @@ mod app {
     }
 
     // We should not pre-empt this so that the wide time stamps are correct.
-    #[task(binds = TG1_T0_LEVEL, local = [tg1_timer0, rtc, previous_rtc_timestamp, color_led],
-        shared = [epoch_millis, blink_led_config, tg0_timer0, blink_led, color_led_active], priority = 2)]
+    #[task(binds = TG1_T0_LEVEL, local = [tg1_timer0, rtc, previous_rtc_timestamp, color_led, event_sender],
+        shared = [epoch_millis, blink_led_config, tg0_timer0, blink_led, color_led_active, uptime_millis, time_zone], priority = 2)]
     fn advance_time(mut cx: advance_time::Context) {
     
         let new_time : u64 = cx.local.rtc.get_time_ms();
@@ -497,14 +715,24 @@ mod app {
         // Create a time stamp for this interrupt.
         *cx.local.previous_rtc_timestamp = new_time;
 
+        // the RTC counts from boot
+        cx.shared.uptime_millis.lock(|uptime_millis| {
+            *uptime_millis = new_time;
+        });
+
         let mut timestamp : i64 = 0;
         cx.shared.epoch_millis.lock(|epoch_millis| {
             *epoch_millis = *epoch_millis + (millis_passed as i64);
             timestamp = *epoch_millis;
         });
         
-        let dt = Utc.timestamp_opt(timestamp/1000, 0).unwrap();
-        rprintln!("[{}-{:02}-{:02} {:02}:{:02}:{:02}]", dt.year(), dt.month(), dt.day(),  dt.hour(), dt.minute(), dt.second());
+        // blinking is scheduled in UTC, only the display and colours go local
+        let local = cx.shared.time_zone.lock(|tz| tz.local_epoch_millis(timestamp));
+
+        match UtcDateTime::from_epoch_millis(local) {
+            Ok(dt) => rprintln!("[{}-{:02}-{:02} {:02}:{:02}:{:02}]", dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second),
+            Err(_) => rprintln!("[{} ms]", local),
+        }
 
         let mut end_blinking : bool = false;
         let mut start_blinking : bool = false;
@@ -526,6 +754,12 @@ mod app {
             }
         });
 
+        if start_blinking {
+            send_event(cx.local.event_sender, Event::BlinkStarted(timestamp));
+        } else if end_blinking {
+            send_event(cx.local.event_sender, Event::BlinkEnded(timestamp));
+        }
+
         // Set the interrupt parameters for the timer that triggers blinking
         cx.shared.tg0_timer0.lock(|tg0_timer0| {
             if end_blinking {
@@ -552,7 +786,7 @@ mod app {
         let mut color = RGB{r: 0, g: 0, b: 0};
         cx.shared.color_led_active.lock(|active| {
             if *active {
-                color = get_led_color(timestamp);
+                color = get_led_color(local);
             }
             cx.local.color_led.write(brightness([color].iter().cloned(), 10)).unwrap();
         });
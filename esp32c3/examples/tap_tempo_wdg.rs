@@ -34,7 +34,10 @@ mod app {
         esp32c3_systimer::{ExtU64, Systimer},
     };
 
-    use shared::shift_register::ShiftRegister;
+    use shared::tempo::TempoEstimator;
+
+    // follow the taps once the estimate is this sure of itself, 0..=100
+    const MIN_CONFIDENCE: u8 = 40;
     
 
     #[shared]
@@ -46,8 +49,7 @@ mod app {
     struct Local {
         button: Gpio9<Input<PullUp>>,
         led: Gpio7<Output<PushPull>>,
-        shift_reg: ShiftRegister,
-        old_ticks: u64,
+        tempo: TempoEstimator,
         wdt0: Wdt<TIMG0>,
     }
 
@@ -77,15 +79,13 @@ mod app {
 
         let _syst = SystemTimer::new(peripherals.SYSTIMER);
 
-        let old_ticks = SystemTimer::now();
-
         // configure button on GPIO9 (interrupt) and LED on GPIO7
         let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
         let mut led = io.pins.gpio7.into_push_pull_output();
         let mut button: esp32c3_hal::gpio::GpioPin<Input<PullUp>, 9> = io.pins.gpio9.into_pull_up_input();
         button.listen(esp32c3_hal::gpio::Event::FallingEdge);
 
-        let shift_reg = ShiftRegister::new();
+        let tempo = TempoEstimator::default();
 
         // initialise LED to low
         led.set_low().unwrap();
@@ -98,8 +98,7 @@ mod app {
         } , Local {
             led,
             button, 
-            shift_reg,
-            old_ticks,
+            tempo,
             wdt0,
         })
     }
@@ -112,38 +111,32 @@ mod app {
     }
 
     // button task to trigger whenever button is pressed. Updates led_switch to true whenever called
-    #[task(binds = GPIO, local = [button, shift_reg, old_ticks, wdt0], shared = [timer0], priority = 2)]
+    #[task(binds = GPIO, local = [button, tempo, wdt0], shared = [timer0], priority = 2)]
     fn button(mut cx: button::Context) {
 
         rprintln!("Feeding Watchdog!");
         // feed the watchdog
         cx.local.wdt0.feed();
 
-        let new_ticks = SystemTimer::now();
-        // convert ticks to ms by div by 16,384 (approximately correct but more efficient than accurate division of 16,000)
-        // divide by two to ensure on -> off is written to shift reg
-        let duration_ms = ((new_ticks - *cx.local.old_ticks) >> 14) >> 1;
-        
-
-        cx.local.shift_reg.insert(duration_ms);
-        rprintln!("inserted {:?}ms into shift reg", duration_ms);
-        
-        if new_ticks == *cx.local.old_ticks {
-            rprintln!("Error: Timer is acting funny!");
-        } 
-        
-        *cx.local.old_ticks = new_ticks;
-
-        if cx.local.shift_reg.valid_entries() {
-          cx.shared.timer0.lock(|timer0| {
-              timer0.unlisten();
-              timer0.reset_counter();
-              timer0.start(cx.local.shift_reg.avg().millis());
-              rprintln!("Average value is: {}ms", cx.local.shift_reg.avg());
-              timer0.listen();
-          });
+        // raw 16 MHz ticks, the estimator converts them
+        let ticks = SystemTimer::now();
+        // one line per press, grep these out of the RTT log to record a sequence
+        rprintln!("tap {}", ticks);
+        if let Some(tempo) = cx.local.tempo.tap(ticks) {
+            rprintln!("{} BPM, {}ms, confidence {}", tempo.bpm(), tempo.period_millis(), tempo.confidence);
+
+            if tempo.confidence >= MIN_CONFIDENCE {
+              // divide by two to ensure on -> off happens once per tap
+              let half_period_ms = tempo.period_millis() / 2;
+              cx.shared.timer0.lock(|timer0| {
+                  timer0.unlisten();
+                  timer0.reset_counter();
+                  timer0.start(half_period_ms.millis());
+                  timer0.listen();
+              });
+            }
         }
-        
+
         cx.local.button.clear_interrupt();
     }
 
@@ -0,0 +1,382 @@
+//! Reliable request/response delivery
+//!
+//! Stop-and-wait ARQ over a `Link`: every command goes out under a fresh
+//! `Seq` and is resent until the response echoing that `Seq` arrives. The
+//! wait for a response doubles with each resend, up to
+//! `ArqConfig::max_timeout`. After `ArqConfig::retries` resends, or once
+//! `ArqConfig::deadline` has passed, the request fails with an `ArqError`
+//! instead of hanging on a device that is not there.
+//!
+//! Resending is safe for commands with side effects, the device replays its
+//! cached response to a repeated `Seq` and command rather than applying the
+//! command again (see `shared::dedup`).
+//!
+//! Given a key (`Arq::set_key`) commands go out as authenticated frames, see
+//! `shared::auth`. Each resend is signed under a new counter, the device
+//! rejects a counter it has seen before but still recognises the `Seq`.
+
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use shared::{
+    auth::{Key, Signer},
+    deserialize_crc_cobs,
+    encoded_size::{max_frame_len, FrameBuf},
+    error::ErrorCode,
+    event::Event,
+    frame_accumulator::FrameAccumulator,
+    Command, DevId, Faults, Response, Seq, Upstream,
+};
+
+const IN_SIZE: usize = max_frame_len::<Upstream>();
+
+/// Read timeout for a port used as a `Link`, short compared to any response timeout
+pub const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A decoded frame from the device, or why it could not be decoded
+pub type Received = Result<(Seq, Upstream), Faults>;
+
+/// Byte transport to the device
+///
+/// `recv` waits at most a short poll interval and returns `Ok(0)` if nothing
+/// arrived in that time, the retry timing is up to `Arq`.
+pub trait Link {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+/// Serial ports and `FaultyPort`s, a read timeout means nothing arrived
+impl<P: Read + Write> Link for P {
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.write_all(frame)?;
+        self.flush()
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.read(buf) {
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => Ok(0),
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArqConfig {
+    /// resends of an unanswered request before giving up
+    pub retries: u32,
+    /// wait for the response to the first attempt
+    pub timeout: Duration,
+    /// the wait doubles per resend, up to this
+    pub max_timeout: Duration,
+    /// overall limit per request, whatever retries are left
+    pub deadline: Option<Duration>,
+}
+
+impl Default for ArqConfig {
+    fn default() -> Self {
+        ArqConfig {
+            retries: 5,
+            timeout: Duration::from_millis(500),
+            max_timeout: Duration::from_secs(4),
+            deadline: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+impl ArqConfig {
+    /// Time to wait for a response to attempt `attempt`, the first being 0
+    pub fn timeout_for(&self, attempt: u32) -> Duration {
+        self.timeout
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_timeout.max(self.timeout))
+    }
+}
+
+/// What happened to the requests so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArqStats {
+    pub requests: u32,
+    pub resends: u32,
+    /// attempts that saw no response in time
+    pub timeouts: u32,
+    /// responses that failed to decode
+    pub corrupt: u32,
+    /// the device could not read the request
+    pub rejected: u32,
+    /// responses to an earlier `Seq`, typically replays of a duplicated request
+    pub stale: u32,
+}
+
+#[derive(Debug)]
+pub enum ArqError {
+    Io(io::Error),
+    /// the command could not be encoded
+    Encode(Faults),
+    /// broadcasts are never answered, send them with `Arq::send`
+    Broadcast,
+    /// the device rejected the key, or a counter it had already seen
+    AuthFailed,
+    /// no response after the first attempt and every resend
+    NoResponse {
+        attempts: u32,
+    },
+    /// `ArqConfig::deadline` passed before a response arrived
+    Deadline {
+        attempts: u32,
+        elapsed: Duration,
+    },
+}
+
+impl fmt::Display for ArqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArqError::Io(e) => write!(f, "link error: {}", e),
+            ArqError::Encode(fault) => write!(f, "command could not be encoded: {:?}", fault),
+            ArqError::Broadcast => write!(f, "broadcasts are never answered"),
+            ArqError::AuthFailed => write!(f, "{}", ErrorCode::AuthFailed.description()),
+            ArqError::NoResponse { attempts } => {
+                write!(f, "no response after {} attempt(s)", attempts)
+            }
+            ArqError::Deadline { attempts, elapsed } => {
+                write!(
+                    f,
+                    "no response within {:?} ({} attempt(s))",
+                    elapsed, attempts
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArqError {}
+
+impl From<io::Error> for ArqError {
+    fn from(e: io::Error) -> Self {
+        ArqError::Io(e)
+    }
+}
+
+impl From<Faults> for ArqError {
+    fn from(fault: Faults) -> Self {
+        ArqError::Encode(fault)
+    }
+}
+
+impl From<ArqError> for io::Error {
+    fn from(e: ArqError) -> Self {
+        let kind = match &e {
+            ArqError::Io(e) => return io::Error::new(e.kind(), e.to_string()),
+            ArqError::Encode(_) | ArqError::Broadcast => ErrorKind::InvalidInput,
+            ArqError::AuthFailed => ErrorKind::PermissionDenied,
+            ArqError::NoResponse { .. } | ArqError::Deadline { .. } => ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+pub struct Arq<L: Link> {
+    link: L,
+    config: ArqConfig,
+    seq: Seq,
+    signer: Option<Signer>,
+    out_buf: FrameBuf<Command>,
+    rx_frames: FrameAccumulator<IN_SIZE>,
+    // bytes received but not yet pushed through `rx_frames`
+    rx_buf: [u8; 64],
+    rx_pos: usize,
+    rx_len: usize,
+    stats: ArqStats,
+}
+
+impl<L: Link> Arq<L> {
+    pub fn new(link: L, config: ArqConfig) -> Self {
+        Arq {
+            link,
+            config,
+            seq: initial_seq(),
+            signer: None,
+            out_buf: FrameBuf::new(),
+            rx_frames: FrameAccumulator::new(),
+            rx_buf: [0; 64],
+            rx_pos: 0,
+            rx_len: 0,
+            stats: ArqStats::default(),
+        }
+    }
+
+    /// Authenticate every command from now on with `key`
+    pub fn set_key(&mut self, key: Key) {
+        self.signer = Some(Signer::new(key, initial_counter()));
+    }
+
+    pub fn link(&self) -> &L {
+        &self.link
+    }
+
+    pub fn config(&self) -> &ArqConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &ArqStats {
+        &self.stats
+    }
+
+    /// Frames dropped for not fitting the receive buffer
+    pub fn dropped_frames(&self) -> u32 {
+        self.rx_frames.dropped_frames()
+    }
+
+    /// Send `cmd` once under a fresh `Seq`, without waiting for a response
+    pub fn send(&mut self, cmd: &Command) -> Result<Seq, ArqError> {
+        self.seq = self.seq.wrapping_add(1);
+        self.transmit(self.seq, cmd)?;
+        Ok(self.seq)
+    }
+
+    fn transmit(&mut self, seq: Seq, cmd: &Command) -> Result<(), ArqError> {
+        let frame = match &mut self.signer {
+            Some(signer) => self.out_buf.encode_signed(seq, cmd, signer)?,
+            None => self.out_buf.encode(seq, cmd)?,
+        };
+        self.link.send(frame)?;
+        Ok(())
+    }
+
+    /// Send `cmd` and return the device's response, resending as needed
+    ///
+    /// Events arriving in the meantime are handed to `on_event`.
+    pub fn request(
+        &mut self,
+        cmd: &Command,
+        mut on_event: impl FnMut(Event, DevId),
+    ) -> Result<Response, ArqError> {
+        if cmd.is_broadcast() {
+            return Err(ArqError::Broadcast);
+        }
+
+        // a new sequence number per command, resends reuse it
+        self.seq = self.seq.wrapping_add(1);
+        let seq = self.seq;
+        self.stats.requests += 1;
+
+        let start = Instant::now();
+        let deadline = self.config.deadline.map(|d| start + d);
+
+        for attempt in 0..=self.config.retries {
+            if attempt > 0 {
+                self.stats.resends += 1;
+            }
+            // the same frame every time, unless signed under a new counter
+            self.transmit(seq, cmd)?;
+
+            // discard any partial frame left over from a previous attempt
+            self.rx_frames.reset();
+            let mut until = Instant::now() + self.config.timeout_for(attempt);
+            if let Some(deadline) = deadline {
+                until = until.min(deadline);
+            }
+
+            loop {
+                match self.next_frame(until)? {
+                    None => {
+                        self.stats.timeouts += 1;
+                        break;
+                    }
+                    // events may arrive at any time, the response is still to come
+                    Some(Ok((_, Upstream::Event(event, dev_id)))) => on_event(event, dev_id),
+                    Some(Ok((rsp_seq, Upstream::Response(rsp)))) if rsp_seq == seq => {
+                        return Ok(rsp)
+                    }
+                    // the device could not read the request, so it cannot tell which one it was
+                    Some(Ok((_, Upstream::Response(Response::NotOK | Response::ParseError)))) => {
+                        self.stats.rejected += 1;
+                        break;
+                    }
+                    // resending the same command will not pass either
+                    Some(Ok((
+                        _,
+                        Upstream::Response(Response::Error(ErrorCode::AuthFailed, _)),
+                    ))) => return Err(ArqError::AuthFailed),
+                    Some(Ok((_, Upstream::Response(Response::Error(code, _)))))
+                        if code.is_transport() =>
+                    {
+                        self.stats.rejected += 1;
+                        break;
+                    }
+                    Some(Ok(_)) => self.stats.stale += 1,
+                    // corrupted response, a resend makes the device replay it
+                    Some(Err(_)) => {
+                        self.stats.corrupt += 1;
+                        break;
+                    }
+                }
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(ArqError::Deadline {
+                    attempts: attempt + 1,
+                    elapsed: start.elapsed(),
+                });
+            }
+        }
+
+        Err(ArqError::NoResponse {
+            attempts: self.config.retries + 1,
+        })
+    }
+
+    /// Next frame from the device, `None` if there was none within `timeout`
+    pub fn poll(&mut self, timeout: Duration) -> io::Result<Option<Received>> {
+        self.next_frame(Instant::now() + timeout)
+    }
+
+    fn next_frame(&mut self, until: Instant) -> io::Result<Option<Received>> {
+        loop {
+            while self.rx_pos < self.rx_len {
+                let b = self.rx_buf[self.rx_pos];
+                self.rx_pos += 1;
+                if let Some(frame) = self.rx_frames.push(b) {
+                    return Ok(Some(deserialize_crc_cobs(frame)));
+                }
+            }
+            if Instant::now() >= until {
+                return Ok(None);
+            }
+            self.rx_len = self.link.recv(&mut self.rx_buf)?;
+            self.rx_pos = 0;
+        }
+    }
+}
+
+// Start from a time based sequence number so that a restarted host rarely
+// reuses the one cached by the device. Nothing depends on it: the device
+// also compares the command, a different one under the same `Seq` is applied.
+fn initial_seq() -> Seq {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as Seq)
+        .unwrap_or(0)
+}
+
+// Microseconds since the epoch, ahead of any counter an earlier run used
+// unless the clock went back
+fn initial_counter() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+#[test]
+fn timeout_doubles_up_to_the_limit() {
+    let config = ArqConfig {
+        timeout: Duration::from_millis(100),
+        max_timeout: Duration::from_millis(500),
+        ..Default::default()
+    };
+    let waits: Vec<_> = (0..5).map(|a| config.timeout_for(a).as_millis()).collect();
+    assert_eq!(waits, [100, 200, 400, 500, 500]);
+    assert_eq!(config.timeout_for(u32::MAX), Duration::from_millis(500));
+}
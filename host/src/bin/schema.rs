@@ -0,0 +1,9 @@
+//! Print the protocol schema of `host::schema` as JSON
+//!
+//! cargo run --bin schema > ../docs/schema/ssmarshal.json
+//! cargo run --bin schema --features postcard > ../docs/schema/postcard.json
+
+fn main() -> Result<(), host::schema::TraceError> {
+    print!("{}", host::schema::to_json()?);
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! Transport wrapper that injects faults into the serial traffic
+//!
+//! Every `write` is treated as one encoded frame and passed through
+//! `FaultInjector::corrupt_frame`. Reads can optionally be corrupted byte by
+//! byte, so that both the device and the host recovery paths get exercised.
+
+use std::io::{Read, Result, Write};
+
+use shared::fault_injector::{FaultConfig, FaultInjector, FaultStats};
+
+pub struct FaultyPort<P> {
+    inner: P,
+    tx: FaultInjector,
+    rx: Option<FaultInjector>,
+}
+
+impl<P> FaultyPort<P> {
+    /// Corrupt outgoing frames only
+    pub fn new(inner: P, seed: u64, config: FaultConfig) -> Self {
+        FaultyPort {
+            inner,
+            tx: FaultInjector::new(seed, config),
+            rx: None,
+        }
+    }
+
+    /// Corrupt outgoing frames and the incoming byte stream
+    pub fn both_ways(inner: P, seed: u64, config: FaultConfig) -> Self {
+        FaultyPort {
+            inner,
+            tx: FaultInjector::new(seed, config),
+            // different stream, same settings
+            rx: Some(FaultInjector::new(seed.rotate_left(32) ^ 1, config)),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn tx_stats(&self) -> &FaultStats {
+        self.tx.stats()
+    }
+
+    pub fn rx_stats(&self) -> Option<&FaultStats> {
+        self.rx.as_ref().map(|rx| rx.stats())
+    }
+}
+
+impl<P: Write> Write for FaultyPort<P> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut out = vec![0u8; 2 * buf.len() + 16];
+        let corrupted = self.tx.corrupt_frame(buf, &mut out);
+        self.inner.write_all(corrupted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<P: Read> Read for FaultyPort<P> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let rx = match &mut self.rx {
+            Some(rx) => rx,
+            None => return self.inner.read(buf),
+        };
+
+        // keep reading until at least one byte survives, so that a dropped
+        // byte does not look like end of stream to the caller
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..n {
+                if let Some(b) = rx.corrupt_byte(buf[i]) {
+                    buf[kept] = b;
+                    kept += 1;
+                }
+            }
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
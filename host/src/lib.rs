@@ -1,3 +1,8 @@
+pub mod arq;
+pub mod fault;
+pub mod schema;
+pub mod when;
+
 use serial2::SerialPort;
 use std::io::Result;
 use std::time::Duration;
@@ -11,7 +16,7 @@ static COM_PATH: &str = "/dev/ttyUSB0";
 #[cfg(target_os = "windows")]
 static COM_PATH: &str = "COM3";
 
-// A one second timeout
+// A one second write timeout, reads only wait a poll interval as `arq::Arq` keeps time
 const TIME_OUT: Duration = Duration::from_millis(1000);
 
 pub fn open() -> Result<SerialPort> {
@@ -20,7 +25,7 @@ pub fn open() -> Result<SerialPort> {
     port.set_dtr(true)?;
     port.set_rts(true)?;
     port.set_write_timeout(TIME_OUT)?;
-    port.set_read_timeout(TIME_OUT)?;
+    port.set_read_timeout(arq::POLL_INTERVAL)?;
 
     Ok(port)
 }
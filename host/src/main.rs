@@ -6,236 +6,598 @@
 //!
 //! Run on host `cd host`
 //!
-//! cargo run
+//! cargo run            (runs the COMMANDS SEQUENCE in `main`)
+//! cargo run -- get     (reads back the device state)
+//! cargo run -- listen  (prints device events)
+//! cargo run -- batch set-time rgb:on blink-in:5:10:6  (applies the actions together)
+//! cargo run -- blink-at 2026-10-17T17:30:00+03:00 10 6  (or +90s, +5m from now)
+//! cargo run -- set-tz EET-2EEST,M3.5.0/3,M10.5.0/4  (local time for the colour schedule)
 //!
 
 // Rust dependencies
-use std::{io::{Read, ErrorKind}, mem::size_of, time::Duration};
+use std::{env, fs, io::ErrorKind, path::PathBuf, time::Duration};
 
 // Libraries
-use corncobs::{max_encoded_len, ZERO};
-use serial2::SerialPort;
+use clap::{Args, Parser, Subcommand};
 use chrono::prelude::*;
 
 // Application dependencies
-use host::open;
-use shared::{deserialize_crc_cobs, serialize_crc_cobs, Command, Message, Response, Faults, date_time::UtcDateTime}; // local library
+use host::{arq::{Arq, ArqConfig, ArqStats, Link}, fault::FaultyPort, open, when::When};
+use shared::{auth::{self, Key}, fault_injector::FaultConfig, batch::{self, BATCH_LEN}, get, Command, Action, Id, Parameter, Response, Faults, DevId, Upstream, address::{self, BROADCAST}, event::Event, date_time::UtcDateTime, time_zone, hello::{HelloInfo, CAP_EPOCH_TIME, CAP_EVENTS, PROTOCOL_VERSION}, wire}; // local library
 
-const CMD_TIMEOUT_SECS : Duration = Duration::from_secs(2); 
+#[derive(Parser, Debug)]
+#[command(about = "RTIC2 - Reliable Serial Communication: Host Application")]
+struct Cli {
+    /// Without a subcommand the COMMANDS SEQUENCE in `main` is executed
+    #[command(subcommand)]
+    command: Option<CliCommand>,
 
-const IN_SIZE: usize = max_encoded_len(size_of::<Response>() + size_of::<u32>());
-const OUT_SIZE: usize = max_encoded_len(size_of::<Command>() + size_of::<u32>());
+    /// Device address on the bus, decimal or 0x/0b prefixed, `broadcast` for all devices
+    #[arg(long, default_value = "1", value_parser = parse_addr)]
+    addr: DevId,
 
-type InBuf = [u8; IN_SIZE];
-type OutBuf = [u8; OUT_SIZE];
+    #[command(flatten)]
+    retry: RetryArgs,
+
+    #[command(flatten)]
+    auth: AuthArgs,
+
+    #[command(flatten)]
+    faults: FaultArgs,
+}
+
+/// Retransmission, how long to keep trying before giving up on the device
+#[derive(Args, Debug)]
+struct RetryArgs {
+    /// Resends of an unanswered request before giving up
+    #[arg(long, default_value_t = 5)]
+    retries: u32,
+    /// Wait for the first response in milliseconds, doubled on every resend
+    #[arg(long, default_value_t = 500)]
+    timeout_ms: u64,
+    /// Give up on a request after this many seconds, 0 for no limit
+    #[arg(long, default_value_t = 30)]
+    deadline_secs: u64,
+}
+
+impl RetryArgs {
+    fn config(&self) -> ArqConfig {
+        ArqConfig {
+            retries: self.retries,
+            timeout: Duration::from_millis(self.timeout_ms),
+            deadline: (self.deadline_secs > 0).then(|| Duration::from_secs(self.deadline_secs)),
+            ..ArqConfig::default()
+        }
+    }
+}
+
+/// Authentication, for firmware built with the `auth` feature
+#[derive(Args, Debug)]
+struct AuthArgs {
+    /// File holding the pre-shared key as 64 hex digits, else taken from AUTH_KEY
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+}
+
+impl AuthArgs {
+    // `None` if neither a key file nor AUTH_KEY is given, commands then go out unsigned
+    fn key(&self) -> Result<Option<Key>, std::io::Error> {
+        let (hex, source) = match &self.key_file {
+            Some(path) => (fs::read_to_string(path)?, path.display().to_string()),
+            None => match env::var("AUTH_KEY") {
+                Ok(hex) => (hex, "AUTH_KEY".to_string()),
+                Err(_) => return Ok(None),
+            },
+        };
+        match auth::parse_key(hex.trim()) {
+            Some(key) => Ok(Some(key)),
+            None => Err(std::io::Error::new(ErrorKind::InvalidInput, format!("{}: expected a key of {} hex digits", source, 2 * auth::KEY_LEN))),
+        }
+    }
+}
+
+/// Fault injection, to show that device and host recover from corrupted frames
+#[derive(Args, Debug)]
+struct FaultArgs {
+    /// Seed for the fault injector, fault injection is off unless given
+    #[arg(long)]
+    fault_seed: Option<u64>,
+    /// Probability of flipping a bit in a frame
+    #[arg(long, default_value_t = 0.0)]
+    flip: f32,
+    /// Probability of dropping a byte from a frame
+    #[arg(long, default_value_t = 0.0)]
+    drop: f32,
+    /// Probability of truncating a frame
+    #[arg(long, default_value_t = 0.0)]
+    truncate: f32,
+    /// Probability of sending a frame twice
+    #[arg(long, default_value_t = 0.0)]
+    duplicate: f32,
+    /// Probability of inserting a garbage frame
+    #[arg(long, default_value_t = 0.0)]
+    garbage: f32,
+    /// Also corrupt the responses, `flip` and `drop` then apply per byte
+    #[arg(long)]
+    fault_rx: bool,
+}
+
+impl FaultArgs {
+    fn config(&self) -> FaultConfig {
+        FaultConfig {
+            bit_flip: self.flip,
+            drop_byte: self.drop,
+            truncate: self.truncate,
+            duplicate: self.duplicate,
+            garbage: self.garbage,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Read back and print the device state
+    Get,
+    /// Print device events as they arrive, until interrupted
+    Listen,
+    /// Apply up to four actions together, all or none of them:
+    /// set-time, blink-off, blink-now:DUR:FREQ, blink-in:SECS:DUR:FREQ,
+    /// blink-at:WHEN:DUR:FREQ, rgb:on|off, tz:TZ
+    Batch {
+        #[arg(required = true, num_args = 1..=BATCH_LEN, value_parser = parse_action)]
+        actions: Vec<Action>,
+    },
+    /// Blink at a given time, for a duration and at a frequency
+    BlinkAt {
+        /// RFC 3339 such as 2026-10-17T14:30:00Z or 2026-10-17T17:30:00+03:00,
+        /// or from now such as +90s, +5m or +1h30m
+        when: When,
+        /// Seconds to blink for
+        duration_secs: u32,
+        /// Blink frequency in Hz
+        freq_hz: u32,
+    },
+    /// Set the time zone the device keeps local time in
+    SetTz {
+        /// POSIX TZ string, UTC0, <+0530>-5:30 or EET-2EEST,M3.5.0/3,M10.5.0/4 for Finland
+        #[arg(value_parser = parse_tz)]
+        tz: time_zone::TimeZone,
+    },
+}
+
+// Everything needed to talk to one device
+struct Session<P: Link> {
+    arq: Arq<P>,
+    // device the commands are addressed to, or `BROADCAST`
+    addr: DevId,
+    // result of the handshake, commands the device does not advertise are refused
+    device: Option<HelloInfo>,
+}
 
 fn main() -> Result<(), std::io::Error> {
 
-    // set to 1 to enable bit flip detection test
-    let bit_flip_test : bool = false;
+    let cli = Cli::parse();
 
     println!("\n\nRTIC2 - Reliable Serial Communication: Host Application\n");
 
-    // get current time
-    let utc : DateTime<Utc> = Utc::now();
-    let mut port = open()?;
+    let port = open()?;
 
-    port.set_read_timeout(CMD_TIMEOUT_SECS)?;
-    println!("Command timeout set to {:?} second(s).\n", port.get_read_timeout().unwrap().as_secs());
+    let config = cli.retry.config();
+    println!("Command timeout {:?}, doubled on each of up to {} resend(s).\n", config.timeout, config.retries);
 
-    let mut out_buf = [0u8; OUT_SIZE];
-    let mut in_buf = [0u8; IN_SIZE];
+    let key = cli.auth.key()?;
+    if key.is_some() {
+        println!("Commands are authenticated with the pre-shared key.\n");
+    }
+
+    let seed = match cli.faults.fault_seed {
+        Some(seed) => seed,
+        None => return run(&mut Session::new(&port, cli.addr, config, key), &cli.command),
+    };
+
+    println!("Injecting faults with seed {}: {:?}\n", seed, cli.faults.config());
+    let faulty = if cli.faults.fault_rx {
+        FaultyPort::both_ways(&port, seed, cli.faults.config())
+    } else {
+        FaultyPort::new(&port, seed, cli.faults.config())
+    };
+
+    let mut session = Session::new(faulty, cli.addr, config, key);
+    let result = run(&mut session, &cli.command);
+
+    println!("Faults injected into requests: {:?}", session.arq.link().tx_stats());
+    println!("Retransmissions: {:?}", session.arq.stats());
+    if let Some(stats) = session.arq.link().rx_stats() {
+        println!("Faults injected into responses: {:?}", stats);
+    }
+    result
+}
+
+fn run<P: Link>(session: &mut Session<P>, command: &Option<CliCommand>) -> Result<(), std::io::Error> {
+
+    if session.addr == BROADCAST {
+        // nobody answers a broadcast, so there is no handshake and nothing to read back
+        println!("Broadcasting to every device, no responses expected.\n");
+        return match command {
+            Some(CliCommand::Get) => Err(std::io::Error::new(ErrorKind::InvalidInput, "get needs a single device, pass --addr")),
+            Some(CliCommand::Listen) => session.listen(),
+            Some(CliCommand::Batch { actions }) => session.execute(&batch_cmd(actions, session.addr)),
+            Some(CliCommand::BlinkAt { when, duration_secs, freq_hz }) => session.execute(&blink_at_cmd(when, *duration_secs, *freq_hz, session.addr)?),
+            Some(CliCommand::SetTz { tz }) => session.execute(&Command::Action(Action::SetTimeZone(*tz), session.addr)),
+            None => command_sequence(session),
+        };
+    }
+
+    // HANDSHAKE
+    // Find out which firmware is on the other end before sending anything else
+    session.handshake()?;
+
+    match command {
+        Some(CliCommand::Get) => print_state(session),
+        Some(CliCommand::Listen) => session.listen(),
+        Some(CliCommand::Batch { actions }) => session.execute(&batch_cmd(actions, session.addr)),
+        Some(CliCommand::BlinkAt { when, duration_secs, freq_hz }) => session.execute(&blink_at_cmd(when, *duration_secs, *freq_hz, session.addr)?),
+        Some(CliCommand::SetTz { tz }) => session.execute(&Command::Action(Action::SetTimeZone(*tz), session.addr)),
+        None => command_sequence(session),
+    }
+}
+
+// One action of the `batch` subcommand, times are taken when parsing
+fn parse_action(s: &str) -> Result<Action, String> {
+    if let Some(rest) = s.strip_prefix("blink-at:") {
+        // the time has colons of its own, duration and frequency are the last two fields
+        let mut fields = rest.rsplitn(3, ':');
+        let (freq, dur, when) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(freq), Some(dur), Some(when)) => (freq, dur, when),
+            _ => return Err(format!("`{}`: expected blink-at:WHEN:DUR:FREQ", s)),
+        };
+        let num = |f: &str| f.parse().map_err(|_| format!("`{}`: `{}` is not a number", s, f));
+        return Ok(Action::BlinkAt(utc_at(&when.parse()?)?, num(dur)?, num(freq)?));
+    }
+    if let Some(tz) = s.strip_prefix("tz:") {
+        return Ok(Action::SetTimeZone(parse_tz(tz)?));
+    }
+    let fields: Vec<&str> = s.split(':').collect();
+    let num = |i: usize| -> Result<u32, String> {
+        fields.get(i).and_then(|f| f.parse().ok()).ok_or_else(|| format!("`{}`: field {} is not a number", s, i))
+    };
+    match (fields[0], fields.len()) {
+        ("set-time", 1) => Ok(Action::SetTime(Utc::now().into())),
+        ("blink-off", 1) => Ok(Action::BlinkOff),
+        ("blink-now", 3) => Ok(Action::BlinkNow(num(1)?, num(2)?)),
+        ("blink-in", 4) => Ok(Action::BlinkAt(utc_in(num(1)? as u64), num(2)?, num(3)?)),
+        ("rgb", 2) => match fields[1] {
+            "on" => Ok(Action::SetRgb(true)),
+            "off" => Ok(Action::SetRgb(false)),
+            _ => Err(format!("`{}`: expected rgb:on or rgb:off", s)),
+        },
+        _ => Err(format!("`{}` is not an action", s)),
+    }
+}
+
+fn parse_tz(s: &str) -> Result<time_zone::TimeZone, String> {
+    s.parse().map_err(|e| format!("`{}`: {}", s, e))
+}
+
+fn parse_addr(s: &str) -> Result<DevId, String> {
+    match s {
+        "broadcast" => Ok(BROADCAST),
+        _ => address::parse(s).ok_or_else(|| format!("`{}` is not a device address", s)),
+    }
+}
+
+fn command_sequence<P: Link>(session: &mut Session<P>) -> Result<(), std::io::Error> {
 
     // COMMANDS SEQUENCE
     // Executed once per host program invocation
     // use true/false to enable or disable the used set of command
 
     if true { // set time to current UTC time
-        let cmd = dt_set_cmd();
-        println!("--> Request: {:?}\n", cmd);
-        let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-        println!("<-- Response: {:?}\n", response);
+        let cmd = dt_set_cmd(session.addr);
+        session.execute(&cmd)?;
     }
 
     if false { // turn off blinker right now
-        let cmd = blink_off_cmd();
-        println!("--> Request: {:?}\n", cmd);
-        let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-        println!("<-- Response: {:?}\n", response);  
+        let cmd = blink_off_cmd(session.addr);
+        session.execute(&cmd)?;
     }    
   
     if false { // turn on blinker right now for set duration and frequency
-        let cmd = blink_on_cmd(10, 3);
-        println!("--> Request: {:?}\n", cmd);
-        let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-        println!("<-- Response: {:?}\n", response);
+        let cmd = blink_on_cmd(10, 3, session.addr);
+        session.execute(&cmd)?;
     }
 
-    // Test time for absolute scheduling
-    let mut udt : UtcDateTime = Utc::now().into();
-    udt.minute += 1;
+    // Test time for absolute scheduling, a minute from now
+    let udt : UtcDateTime = utc_in(60);
 
 
     if false {
         // schedule blinker for absolute time for a set duration and frequency
-        // note that this will return an illegal response if attempted before the time is set
-        let cmd = blink_sched_abs_cmd(&udt, 10, 6);
-        println!("--> Request: {:?}\n", cmd);
-        let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-        println!("<-- Response: {:?}\n", response);
+        // note that this will return a TimeNotSet error if attempted before the time is set
+        let cmd = blink_sched_abs_cmd(&udt, 10, 6, session.addr);
+        session.execute(&cmd)?;
     }
 
     if true { // schedule blinker for a time with relative offset to current time
-        let cmd = blink_sched_rel_cmd(5, 10, 6);
-        println!("--> Request: {:?}\n", cmd);
-        let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-        println!("<-- Response: {:?}\n", response);
+        let cmd = blink_sched_rel_cmd(5, 10, 6, session.addr);
+        session.execute(&cmd)?;
     }
 
     if true { // set state of rbg led, true->on : false->off
-        let cmd = set_rgb_on_cmd(true);
-        println!("--> Request: {:?}\n", cmd);
-        let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-        println!("<-- Response: {:?}\n", response);
+        let cmd = set_rgb_on_cmd(true, session.addr);
+        session.execute(&cmd)?;
     }
 
-    //if true {    
-    //    // currently no use for get
-    //    let cmd = Command::Get(0x12, 12, 0b001);
-    //    println!("--> Request: {:?}\n", cmd);
-    //    let response = request(&cmd, &mut port, &mut out_buf, &mut in_buf, bit_flip_test)?;
-    //    println!("<-- Response: {:?}\n", response);
-    //}
+    if false { // set time, turn on the rgb led and schedule blinking, all at once
+        let cmd = batch_cmd(&[dt_set_action(), Action::SetRgb(true), Action::BlinkAt(utc_in(5), 10, 6)], session.addr);
+        session.execute(&cmd)?;
+    }
+
+    Ok(())
+}
+
+fn print_state<P: Link>(session: &mut Session<P>) -> Result<(), std::io::Error> {
+
+    let time = session.get_value(get::TIME, 0)?;
+    let time_set = session.get_value(get::TIME_SET, 0)?;
+    let blink_start = session.get_value(get::BLINK_CONFIG, get::BLINK_START)?;
+    let blink_end = session.get_value(get::BLINK_CONFIG, get::BLINK_END)?;
+    let blink_period = session.get_value(get::BLINK_CONFIG, get::BLINK_PERIOD)?;
+    let blink_active = session.get_value(get::BLINK_CONFIG, get::BLINK_ACTIVE)?;
+    let rgb_active = session.get_value(get::RGB_ACTIVE, 0)?;
+    let uptime = session.get_value(get::UPTIME, 0)?;
+    let utc_offset = session.get_value(get::UTC_OFFSET, 0)?;
+
+    println!("Device state");
+    println!("  time          : {}{}", fmt_epoch_millis(time), if time_set != 0 { "" } else { " (not set)" });
+    // the sign on its own, -30 minutes has no hours to carry it
+    let offset_sign = if utc_offset < 0 { '-' } else { '+' };
+    println!("  local time    : {} (UTC{}{:02}:{:02})", fmt_epoch_millis(time + utc_offset * 60_000).trim_end_matches(" UTC"), offset_sign, utc_offset.unsigned_abs() / 60, utc_offset.unsigned_abs() % 60);
+    println!("  blink start   : {}", fmt_epoch_millis(blink_start));
+    println!("  blink end     : {}", fmt_epoch_millis(blink_end));
+    println!("  blink period  : {} ms", blink_period);
+    println!("  blinking      : {}", if blink_active != 0 { "yes" } else { "no" });
+    println!("  rgb led       : {}", if rgb_active != 0 { "on" } else { "off" });
+    println!("  uptime        : {}", fmt_duration_millis(uptime));
 
     Ok(())
 }
 
-fn dt_set_cmd() -> Command {
+fn print_response(response: &Response) {
+    match response {
+        Response::Error(code, detail) => {
+            println!("<-- Response: {:?}\n    {} (detail: {})\n", response, code.description(), detail);
+        },
+        Response::BatchRejected(index, code, detail) => {
+            println!("<-- Response: {:?}\n    nothing applied, action {}: {} (detail: {})\n", response, index, code.description(), detail);
+        },
+        _ => println!("<-- Response: {:?}\n", response),
+    }
+}
+
+fn print_event(event: &Event, dev_id: DevId) {
+    let what = match event {
+        Event::BlinkStarted(at) => format!("blinking started at {}", fmt_epoch_millis(*at)),
+        Event::BlinkEnded(at) => format!("blinking ended at {}", fmt_epoch_millis(*at)),
+        Event::TimeNotSet => "time not set".to_string(),
+        Event::Rebooted => "rebooted".to_string(),
+        Event::RxOverflow(bytes) => format!("dropped an oversized frame ({} bytes)", bytes),
+    };
+    println!("<-- Event from device {:#05b}: {}\n", dev_id, what);
+}
+
+fn fmt_epoch_millis(millis: i64) -> String {
+    match Utc.timestamp_millis_opt(millis).single() {
+        Some(dt) => dt.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string(),
+        None => format!("<invalid: {} ms>", millis),
+    }
+}
+
+fn fmt_duration_millis(millis: i64) -> String {
+    let secs = millis / 1000;
+    format!("{}h {:02}m {:02}.{:03}s", secs / 3600, (secs / 60) % 60, secs % 60, millis % 1000)
+}
+
+fn dt_set_action() -> Action {
     let utc : DateTime<Utc> = Utc::now();
     let udt : UtcDateTime   = utc.into();
-    let cmd : Command       = Command::Set(0x1, Message::A(udt), 0b001);
-    cmd
-}
-
-fn blink_off_cmd() -> Command {
-    let cmd = Command::Set(0x2, Message::B(0), 0b001);
-    cmd
-}
-fn blink_on_cmd(blk_dur: u32, blk_freq: u32)-> Command {
-    let cmd = Command::Set(0x3, Message::C(blk_dur,blk_freq), 0b001);
-    cmd
-}
-fn blink_sched_abs_cmd(utc_dt: &UtcDateTime, blk_dur: u32, blk_freq: u32) -> Command {
-
-    let tmp_utc_dt = UtcDateTime {
-                                    year:           utc_dt.year, 
-                                    month:          utc_dt.month, 
-                                    day:            utc_dt.day, 
-                                    hour:           utc_dt.hour, 
-                                    minute:         utc_dt.minute, 
-                                    second:         utc_dt.second, 
-                                    nanoseconds:    utc_dt.nanoseconds
-                                };
-
-    let cmd = Command::Set(0x4, Message::D(tmp_utc_dt, blk_dur, blk_freq), 0b001);
-    cmd
-}
-fn blink_sched_rel_cmd(offset_secs: i64, blk_dur: u32, blk_freq: u32) -> Command {
-    let udt         : UtcDateTime   = Utc::now().into();
-    let dt          : DateTime<Utc> = Utc.with_ymd_and_hms( udt.year, 
-                                                            udt.month, 
-                                                            udt.day, 
-                                                            udt.hour,
-                                                        udt.minute, 
-                                                        udt.second
-                                                    ).unwrap();
-    let epoch_millis: i64           = dt.timestamp_millis();
-    let offset      : i64           = epoch_millis + offset_secs*1000;
-    let udt_new     : UtcDateTime   = Utc.timestamp_millis_opt(offset).unwrap().into();
-    let cmd         : Command       = Command::Set(0x4, Message::D(udt_new, blk_dur, blk_freq), 0b001);
-    cmd
-}
-fn set_rgb_on_cmd(state: bool) -> Command {
-    let led_state: u32        = if state {1} else {0};
-    let cmd      : Command    = Command::Set(0x5, Message::B(led_state), 0b001);
-    cmd
-}
-
-fn get_response(in_buf: &mut InBuf) -> Result<Response, ()> {
-    
-    // Get response and check for errors
-    let rsp = deserialize_crc_cobs(in_buf);
-    match rsp {
-        Ok(r) => {
-            return Ok(r);
+    Action::SetTime(udt)
+}
+fn dt_set_cmd(addr: DevId) -> Command {
+    Command::Action(dt_set_action(), addr)
+}
+
+fn blink_off_cmd(addr: DevId) -> Command {
+    Command::Action(Action::BlinkOff, addr)
+}
+fn blink_on_cmd(blk_dur: u32, blk_freq: u32, addr: DevId)-> Command {
+    Command::Action(Action::BlinkNow(blk_dur, blk_freq), addr)
+}
+fn blink_sched_abs_cmd(utc_dt: &UtcDateTime, blk_dur: u32, blk_freq: u32, addr: DevId) -> Command {
+    Command::Action(Action::BlinkAt(*utc_dt, blk_dur, blk_freq), addr)
+}
+// Current time plus `offset_secs`, whole seconds
+fn utc_in(offset_secs: u64) -> UtcDateTime {
+    let now : UtcDateTime = Utc::now().into();
+    UtcDateTime { nanoseconds: 0, ..now } + Duration::from_secs(offset_secs)
+}
+// `when` in UTC, relative times counted from the current whole second
+fn utc_at(when: &When) -> Result<UtcDateTime, String> {
+    let now : UtcDateTime = Utc::now().into();
+    when.resolve(UtcDateTime { nanoseconds: 0, ..now }).map_err(|e| format!("{:?}: {}", when, e))
+}
+fn blink_at_cmd(when: &When, blk_dur: u32, blk_freq: u32, addr: DevId) -> Result<Command, std::io::Error> {
+    let at = utc_at(when).map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+    println!("Blinking at {}\n", at);
+    Ok(blink_sched_abs_cmd(&at, blk_dur, blk_freq, addr))
+}
+fn blink_sched_rel_cmd(offset_secs: u64, blk_dur: u32, blk_freq: u32, addr: DevId) -> Command {
+    Command::Action(Action::BlinkAt(utc_in(offset_secs), blk_dur, blk_freq), addr)
+}
+fn set_rgb_on_cmd(state: bool, addr: DevId) -> Command {
+    Command::Action(Action::SetRgb(state), addr)
+}
+// at most `BATCH_LEN` actions, the CLI enforces it
+fn batch_cmd(actions: &[Action], addr: DevId) -> Command {
+    Command::Batch(batch::from_actions(actions).expect("too many actions for one batch"), addr)
+}
+
+fn print_fault(fault: &Faults) {
+    match fault {
+        Faults::BitFlipData => {
+            println!("[Error] Detected bit flip in Data or CRC!\n");
+        },
+        Faults::CobsDecode => {
+            println!("[Error] Response frame could not be COBS decoded!\n");
+        },
+        Faults::Truncated => {
+            println!("[Error] Response frame was truncated!\n");
         },
-        Err(e) => {
-            
-            match e {
-                Faults::BitFlipData => {
-                    println!("[Error] Detected bit flip in Data or CRC!\n");
-                },
-            }; 
-            return Ok(Response::NotOK);
+        Faults::Deserialize => {
+            println!("[Error] Response payload could not be deserialized!\n");
+        },
+        Faults::BufferTooSmall => {
+            println!("[Error] Response does not fit in the receive buffer!\n");
+        },
+        Faults::AuthFailed => {
+            println!("[Error] Response failed authentication!\n");
         },
     };
 }
 
-fn request(
-    cmd: &Command,
-    port: &mut SerialPort,
-    out_buf: &mut OutBuf,
-    in_buf: &mut InBuf,
-    bit_flip_test: bool,
-) -> Result<Response, std::io::Error> {
-    
-    let to_write = serialize_crc_cobs(cmd, out_buf, bit_flip_test);
-    let mut tx_complete : bool = false;
+impl<P: Link> Session<P> {
+
+    fn new(port: P, addr: DevId, config: ArqConfig, key: Option<Key>) -> Self {
+        let mut arq = Arq::new(port, config);
+        if let Some(key) = key {
+            arq.set_key(key);
+        }
+        Session {
+            arq,
+            addr,
+            device: None,
+        }
+    }
+
+    fn handshake(&mut self) -> Result<(), std::io::Error> {
 
-    while tx_complete == false {
+        let cmd = Command::Hello(self.addr);
+        // a device built with the other wire backend can only answer ParseError
+        println!("--> Request: {:?} ({} wire format)\n", cmd, wire::BACKEND);
+        let response = self.request(&cmd)?;
 
-        port.write_all(to_write)?;
+        let info = match response {
+            Response::Hello(info) => info,
+            other => {
+                return Err(std::io::Error::new(ErrorKind::Unsupported, format!("device did not answer the handshake: {:?}", other)));
+            },
+        };
 
-        println!("Request written... Awaiting response.\n");
+        println!("<-- Device {:#05b}: firmware {} ({}), protocol v{}, capabilities {:#010b}\n",
+            info.dev_id, info.fw_version(), info.git_hash(), info.protocol_version, info.capabilities);
+
+        if info.protocol_version != PROTOCOL_VERSION {
+            return Err(std::io::Error::new(ErrorKind::Unsupported, format!("device speaks protocol v{}, host speaks v{}", info.protocol_version, PROTOCOL_VERSION)));
+        }
+
+        self.device = Some(info);
+        Ok(())
+    }
+
+    // Read a single value with `Command::Get`, see `shared::get` for the ids
+    fn get_value(&mut self, id: Id, param: Parameter) -> Result<i64, std::io::Error> {
+        match self.request(&Command::Get(id, param, self.addr))? {
+            Response::Data(_, _, value, _) => Ok(value),
+            Response::Error(code, _) => Err(std::io::Error::new(ErrorKind::InvalidData, format!("Get({}, {}) failed: {}", id, param, code.description()))),
+            other => Err(std::io::Error::new(ErrorKind::InvalidData, format!("unexpected response to Get({}, {}): {:?}", id, param, other))),
+        }
+    }
+
+    // Send `cmd` and print the outcome
+    fn execute(&mut self, cmd: &Command) -> Result<(), std::io::Error> {
+        let cmd = &self.compact(cmd);
+        println!("--> Request: {:?}\n", cmd);
+        if cmd.is_broadcast() {
+            self.broadcast(cmd)?;
+            println!("<-- Broadcast, no response\n");
+        } else {
+            let response = self.request(cmd)?;
+            print_response(&response);
+        }
+        Ok(())
+    }
+
+    // Times as epoch timestamps if the device takes them, shorter frames and
+    // no calendar arithmetic on the device. Broadcasts keep the calendar
+    // form, there is no handshake to tell what every device understands.
+    fn compact(&self, cmd: &Command) -> Command {
+        match &self.device {
+            Some(device) if device.capabilities & CAP_EPOCH_TIME != 0 => cmd.clone().into_epoch(),
+            _ => cmd.clone(),
+        }
+    }
+
+    // Send `cmd` once, devices carry out broadcasts without answering
+    fn broadcast(&mut self, cmd: &Command) -> Result<(), std::io::Error> {
+        self.arq.send(cmd)?;
+        Ok(())
+    }
+
+    // Print events until interrupted, a quiet device is not an error
+    fn listen(&mut self) -> Result<(), std::io::Error> {
+        if let Some(device) = &self.device {
+            if device.capabilities & CAP_EVENTS == 0 {
+                println!("[Error] - Device does not send events, nothing to listen to.\n");
+            }
+        }
+        println!("Listening for events, Ctrl-C to stop.\n");
 
-        let mut index: usize = 0;
-        
         loop {
-            
-            let slice = &mut in_buf[index..index + 1];
-            
-            if index < IN_SIZE {
-                index += 1;
+            match self.arq.poll(Duration::from_secs(1))? {
+                Some(Ok((_, Upstream::Event(event, dev_id)))) => print_event(&event, dev_id),
+                Some(Ok((seq, Upstream::Response(rsp)))) => println!("[Error] - Unexpected response (seq {}): {:?}\n", seq, rsp),
+                Some(Err(fault)) => print_fault(&fault),
+                None => {},
             }
-            
-            match port.read_exact(slice) {
-                Ok(_) => {
-                    // do nothing
-                },
-                // check for timeout and re-send packet if detected
-                Err(e) => {
-                    match e.kind() {
-                        ErrorKind::TimedOut => {
-                            println!("[Error] - Request time-out expired!\n");
-                            break;
-                        },
-                        _ => {
-                            println!("[Error] - There was a problem reading a byte from the buffer: {:?}\n", e);
-                            return Err(e);
-                        },
-                    };
-                },
-            };
-
-            if slice[0] == ZERO {
-                println!("Response received!\n");
-                tx_complete = true;
-                break;
+        }
+    }
+
+    fn request(&mut self, cmd: &Command) -> Result<Response, std::io::Error> {
+
+        if let Some(device) = &self.device {
+            if !device.supports(cmd) {
+                return Err(std::io::Error::new(ErrorKind::Unsupported, format!("device does not support {:?}", cmd)));
             }
         }
+
+        let before = *self.arq.stats();
+        let dropped = self.arq.dropped_frames();
+        println!("Request written... Awaiting response.\n");
+
+        let result = self.arq.request(cmd, |event, dev_id| print_event(&event, dev_id));
+        print_retries(&before, self.arq.stats());
+        if self.arq.dropped_frames() > dropped {
+            println!("[Error] - {} oversized frame(s) dropped so far\n", self.arq.dropped_frames());
+        }
+
+        match result {
+            Ok(rsp) => {
+                println!("Response received!\n");
+                Ok(rsp)
+            },
+            Err(e) => {
+                println!("[Error] - {}\n", e);
+                Err(e.into())
+            },
+        }
     }
+}
 
-    // Get response and check for errors
-    Ok(get_response(in_buf).unwrap())
-   
+// What went wrong before the request got through, if anything
+fn print_retries(before: &ArqStats, after: &ArqStats) {
+    let resends = after.resends - before.resends;
+    if resends > 0 {
+        println!("[Error] - Re-sent {} time(s): {} time-out(s), {} corrupt and {} rejected response(s)\n",
+            resends, after.timeouts - before.timeouts, after.corrupt - before.corrupt, after.rejected - before.rejected);
+    }
 }
@@ -0,0 +1,781 @@
+//! Machine readable description of the wire protocol
+//!
+//! `schema()` describes every type that travels in a frame, with its byte
+//! layout under the active `wire` backend, the ids of `Command::Get` and of
+//! the legacy `Command::Set`, and the frame and CRC parameters.
+//! `cargo run --bin schema` prints it as JSON, `docs/schema/` holds the output
+//! for both backends.
+//!
+//! The type descriptions are traced rather than written down: a sample of
+//! every variant goes through a `serde::Serializer` that records the shape
+//! of the value instead of its bytes, the way `serde-reflection` does. Arrays
+//! and tuples look the same to serde and on the wire, a run of equal fields
+//! is reported as an array. `tests/schema.rs` fails when the Rust types, the
+//! samples below and the files in `docs/schema/` disagree.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{de, ser, ser::Impossible, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use shared::{
+    address,
+    auth::{COUNTER_LEN, TAG_LEN},
+    batch::BATCH_LEN,
+    date_time::UtcDateTime,
+    encoded_size::{max_frame_len, max_payload_len, MaxEncodedSize},
+    error::ErrorCode,
+    event::Event,
+    get,
+    hello::{self, HelloInfo, PROTOCOL_VERSION},
+    time_zone::TimeZone,
+    wire, Action, Command, Id, Message, Response, Upstream, CKSUM, CRC_LEN,
+};
+
+/// Shape of a value as serde presents it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// integer or `bool`, by its Rust name
+    Prim(&'static str),
+    Option(Box<Format>),
+    /// `[T; N]`, or a tuple of N equal fields
+    Array(Box<Format>, usize),
+    Tuple(Vec<Format>),
+    /// struct or enum, described in the `Registry`
+    Named(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Container {
+    Struct(Vec<(&'static str, Format)>),
+    /// name and fields of the variants traced so far, by variant index
+    Enum(BTreeMap<u32, (&'static str, Vec<Format>)>),
+}
+
+/// Containers by type name
+pub type Registry = BTreeMap<&'static str, Container>;
+
+/// A value serde cannot describe fully, or two samples that disagree
+#[derive(Debug)]
+pub struct TraceError(String);
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot trace the protocol types: {}", self.0)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl ser::Error for TraceError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        TraceError(msg.to_string())
+    }
+}
+
+impl de::Error for TraceError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        TraceError(msg.to_string())
+    }
+}
+
+/// Record the containers `value` is made of, returns its shape
+pub fn trace<T: ?Sized + Serialize>(
+    registry: &mut Registry,
+    value: &T,
+) -> Result<Format, TraceError> {
+    value.serialize(Tracer { registry })
+}
+
+fn record(
+    registry: &mut Registry,
+    name: &'static str,
+    container: Container,
+) -> Result<Format, TraceError> {
+    let merged = match (registry.remove(name), container) {
+        (None, container) => container,
+        (Some(Container::Enum(mut seen)), Container::Enum(variants)) => {
+            for (index, variant) in variants {
+                match seen.get(&index) {
+                    Some(other) if *other != variant => {
+                        return Err(TraceError(format!(
+                            "{}::{} traced as {:?} and {:?}",
+                            name, variant.0, other.1, variant.1
+                        )))
+                    }
+                    _ => {
+                        seen.insert(index, variant);
+                    }
+                }
+            }
+            Container::Enum(seen)
+        }
+        (Some(seen), container) if seen == container => container,
+        (Some(seen), container) => {
+            return Err(TraceError(format!(
+                "{} traced as {:?} and {:?}",
+                name, seen, container
+            )))
+        }
+    };
+    registry.insert(name, merged);
+    Ok(Format::Named(name))
+}
+
+fn variant(
+    registry: &mut Registry,
+    name: &'static str,
+    index: u32,
+    variant: &'static str,
+    fields: Vec<Format>,
+) -> Result<Format, TraceError> {
+    let variants = BTreeMap::from([(index, (variant, fields))]);
+    record(registry, name, Container::Enum(variants))
+}
+
+fn unsupported(what: &str) -> TraceError {
+    TraceError(format!("{} never travels in a frame", what))
+}
+
+struct Tracer<'r> {
+    registry: &'r mut Registry,
+}
+
+impl<'r> ser::Serializer for Tracer<'r> {
+    type Ok = Format;
+    type Error = TraceError;
+    type SerializeSeq = Impossible<Format, TraceError>;
+    type SerializeTuple = Fields<'r>;
+    type SerializeTupleStruct = Fields<'r>;
+    type SerializeTupleVariant = Fields<'r>;
+    type SerializeMap = Impossible<Format, TraceError>;
+    type SerializeStruct = Fields<'r>;
+    type SerializeStructVariant = Impossible<Format, TraceError>;
+
+    fn serialize_bool(self, _: bool) -> Result<Format, TraceError> {
+        Ok(Format::Prim("bool"))
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<Format, TraceError> {
+        Ok(Format::Prim("i8"))
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<Format, TraceError> {
+        Ok(Format::Prim("i16"))
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<Format, TraceError> {
+        Ok(Format::Prim("i32"))
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<Format, TraceError> {
+        Ok(Format::Prim("i64"))
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<Format, TraceError> {
+        Ok(Format::Prim("u8"))
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<Format, TraceError> {
+        Ok(Format::Prim("u16"))
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<Format, TraceError> {
+        Ok(Format::Prim("u32"))
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<Format, TraceError> {
+        Ok(Format::Prim("u64"))
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Format, TraceError> {
+        Err(unsupported("f32"))
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Format, TraceError> {
+        Err(unsupported("f64"))
+    }
+
+    fn serialize_char(self, _: char) -> Result<Format, TraceError> {
+        Err(unsupported("char"))
+    }
+
+    fn serialize_str(self, _: &str) -> Result<Format, TraceError> {
+        Err(unsupported("str"))
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Format, TraceError> {
+        Err(unsupported("a byte slice"))
+    }
+
+    fn serialize_none(self) -> Result<Format, TraceError> {
+        Err(TraceError(
+            "a `None` sample hides the type inside, use `Some`".to_string(),
+        ))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Format, TraceError> {
+        Ok(Format::Option(Box::new(value.serialize(self)?)))
+    }
+
+    fn serialize_unit(self) -> Result<Format, TraceError> {
+        Ok(Format::Tuple(Vec::new()))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Format, TraceError> {
+        record(self.registry, name, Container::Struct(Vec::new()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        name_of_variant: &'static str,
+    ) -> Result<Format, TraceError> {
+        variant(self.registry, name, index, name_of_variant, Vec::new())
+    }
+
+    // transparent with both backends
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Format, TraceError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        index: u32,
+        name_of_variant: &'static str,
+        value: &T,
+    ) -> Result<Format, TraceError> {
+        let field = trace(self.registry, value)?;
+        variant(self.registry, name, index, name_of_variant, vec![field])
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, TraceError> {
+        Err(unsupported("a length prefixed sequence"))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Fields<'r>, TraceError> {
+        Ok(Fields::new(self.registry, Kind::Tuple))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Fields<'r>, TraceError> {
+        Ok(Fields::new(self.registry, Kind::Tuple))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        name_of_variant: &'static str,
+        _: usize,
+    ) -> Result<Fields<'r>, TraceError> {
+        let kind = Kind::Variant(name, index, name_of_variant);
+        Ok(Fields::new(self.registry, kind))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, TraceError> {
+        Err(unsupported("a map"))
+    }
+
+    fn serialize_struct(self, name: &'static str, _: usize) -> Result<Fields<'r>, TraceError> {
+        Ok(Fields::new(self.registry, Kind::Struct(name)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, TraceError> {
+        Err(unsupported("a struct variant"))
+    }
+}
+
+enum Kind {
+    Tuple,
+    Struct(&'static str),
+    Variant(&'static str, u32, &'static str),
+}
+
+// fields of a tuple, struct or tuple variant, recorded once all are known
+struct Fields<'r> {
+    registry: &'r mut Registry,
+    kind: Kind,
+    fields: Vec<(&'static str, Format)>,
+}
+
+impl<'r> Fields<'r> {
+    fn new(registry: &'r mut Registry, kind: Kind) -> Self {
+        Fields {
+            registry,
+            kind,
+            fields: Vec::new(),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), TraceError> {
+        let format = trace(self.registry, value)?;
+        self.fields.push((name, format));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Format, TraceError> {
+        let formats = || self.fields.iter().map(|(_, f)| f.clone()).collect();
+        match self.kind {
+            Kind::Tuple => match self.fields.first() {
+                Some((_, first))
+                    if self.fields.len() > 1 && self.fields.iter().all(|(_, f)| f == first) =>
+                {
+                    Ok(Format::Array(Box::new(first.clone()), self.fields.len()))
+                }
+                _ => Ok(Format::Tuple(formats())),
+            },
+            Kind::Struct(name) => {
+                let container = Container::Struct(self.fields.clone());
+                record(self.registry, name, container)
+            }
+            Kind::Variant(name, index, name_of_variant) => {
+                variant(self.registry, name, index, name_of_variant, formats())
+            }
+        }
+    }
+}
+
+impl ser::SerializeTuple for Fields<'_> {
+    type Ok = Format;
+    type Error = TraceError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), TraceError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Format, TraceError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Fields<'_> {
+    type Ok = Format;
+    type Error = TraceError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), TraceError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Format, TraceError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for Fields<'_> {
+    type Ok = Format;
+    type Error = TraceError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), TraceError> {
+        self.push("", value)
+    }
+
+    fn end(self) -> Result<Format, TraceError> {
+        Fields::end(self)
+    }
+}
+
+impl ser::SerializeStruct for Fields<'_> {
+    type Ok = Format;
+    type Error = TraceError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), TraceError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Format, TraceError> {
+        Fields::end(self)
+    }
+}
+
+/// Every variant name of the enum `T` in index order, traced or not
+///
+/// serde hands the full list to `Deserializer::deserialize_enum`, the probe
+/// keeps it and gives up.
+pub fn variant_names<T: for<'de> Deserialize<'de>>() -> Option<&'static [&'static str]> {
+    let mut names = None;
+    let _ = T::deserialize(EnumProbe(&mut names));
+    names
+}
+
+struct EnumProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> de::Deserializer<'de> for EnumProbe<'_> {
+    type Error = TraceError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, _: V) -> Result<V::Value, TraceError> {
+        Err(TraceError("not an enum".to_string()))
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _: &'static str,
+        variants: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, TraceError> {
+        *self.0 = Some(variants);
+        Err(TraceError("probed".to_string()))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn sample_date_time() -> UtcDateTime {
+    UtcDateTime {
+        year: 2023,
+        month: 10,
+        day: 17,
+        hour: 14,
+        minute: 30,
+        second: 0,
+        nanoseconds: 0,
+    }
+}
+
+// with a DST rule, a `None` cannot be traced
+fn sample_time_zone() -> TimeZone {
+    "EET-2EEST,M3.5.0/3,M10.5.0/4"
+        .parse()
+        .expect("a valid TZ string")
+}
+
+fn sample_actions() -> Vec<Action> {
+    let dt = sample_date_time();
+    vec![
+        Action::SetTime(dt),
+        Action::BlinkOff,
+        Action::BlinkNow(10, 3),
+        Action::BlinkAt(dt, 10, 3),
+        Action::SetRgb(true),
+        Action::SetTimeEpoch(1_697_553_000_000_000_000),
+        Action::BlinkAtEpoch(1_697_553_000_000_000_000, 10, 3),
+        Action::SetTimeZone(sample_time_zone()),
+    ]
+}
+
+/// One of every `Command` variant, and of everything they carry
+pub fn sample_commands() -> Vec<Command> {
+    let dt = sample_date_time();
+    let mut commands = vec![
+        Command::Set(1, Message::A(dt), 1),
+        Command::Set(2, Message::B(0), 1),
+        Command::Set(3, Message::C(10, 3), 1),
+        Command::Set(4, Message::D(dt, 10, 3), 1),
+        Command::Set(1, Message::E(1_697_553_000_000_000_000), 1),
+        Command::Set(4, Message::F(1_697_553_000_000_000_000, 10, 3), 1),
+        Command::Set(6, Message::G(sample_time_zone()), 1),
+        Command::Get(get::TIME, 0, 1),
+        Command::Hello(1),
+        Command::Batch([(); BATCH_LEN].map(|_| Some(Action::BlinkOff)), 1),
+    ];
+    commands.extend(sample_actions().into_iter().map(|a| Command::Action(a, 1)));
+    commands
+}
+
+/// One of every `Upstream` variant, and of everything they carry
+pub fn sample_upstream() -> Vec<Upstream> {
+    let info = HelloInfo {
+        protocol_version: PROTOCOL_VERSION,
+        fw_version: hello::padded("0.1.0"),
+        git_hash: hello::padded("0123abcd"),
+        dev_id: 1,
+        capabilities: 0,
+    };
+    let codes = [
+        ErrorCode::TimeNotSet,
+        ErrorCode::InvalidDate,
+        ErrorCode::FrequencyOutOfRange,
+        ErrorCode::DurationOutOfRange,
+        ErrorCode::UnknownCommand,
+        ErrorCode::CrcMismatch,
+        ErrorCode::BufferOverflow,
+        ErrorCode::AuthFailed,
+        ErrorCode::InvalidTimeZone,
+    ];
+    let mut responses = vec![
+        Response::Data(get::TIME, 0, 1_697_553_000_000, 1),
+        Response::SetOk,
+        Response::ParseError,
+        Response::NotOK,
+        Response::Illegal,
+        Response::Hello(info),
+        Response::BatchRejected(0, ErrorCode::TimeNotSet, 0),
+    ];
+    responses.extend(codes.map(|code| Response::Error(code, 0)));
+    let events = [
+        Event::BlinkStarted(0),
+        Event::BlinkEnded(0),
+        Event::TimeNotSet,
+        Event::Rebooted,
+        Event::RxOverflow(0),
+    ];
+
+    let mut upstream: Vec<_> = responses.into_iter().map(Upstream::Response).collect();
+    upstream.extend(events.map(|event| Upstream::Event(event, 1)));
+    upstream
+}
+
+/// Registry of every type reachable from `Command` and `Upstream`
+pub fn registry() -> Result<Registry, TraceError> {
+    let mut registry = Registry::new();
+    for cmd in sample_commands() {
+        trace(&mut registry, &cmd)?;
+    }
+    for up in sample_upstream() {
+        trace(&mut registry, &up)?;
+    }
+    Ok(registry)
+}
+
+// (fewest, most) bytes of a primitive under the active backend
+fn prim_bytes(name: &str) -> (usize, usize) {
+    let width: usize = match name {
+        // single bytes are written as they are by both backends
+        "bool" | "u8" | "i8" => return (1, 1),
+        "u16" | "i16" => 2,
+        "u32" | "i32" => 4,
+        _ => 8,
+    };
+    if wire::BACKEND == "postcard" {
+        (1, (width * 8).div_ceil(7))
+    } else {
+        (width, width)
+    }
+}
+
+fn sum(sizes: impl Iterator<Item = (usize, usize)>) -> (usize, usize) {
+    sizes.fold((0, 0), |(min, max), (a, b)| (min + a, max + b))
+}
+
+/// (fewest, most) bytes `wire::serialize` writes for a value of `format`
+pub fn bytes(registry: &Registry, format: &Format) -> (usize, usize) {
+    match format {
+        Format::Prim(name) => prim_bytes(name),
+        // a one byte tag, 0 for `None`
+        Format::Option(inner) => (1, 1 + bytes(registry, inner).1),
+        Format::Array(inner, n) => {
+            let (min, max) = bytes(registry, inner);
+            (n * min, n * max)
+        }
+        Format::Tuple(formats) => sum(formats.iter().map(|f| bytes(registry, f))),
+        Format::Named(name) => match &registry[name] {
+            Container::Struct(fields) => sum(fields.iter().map(|(_, f)| bytes(registry, f))),
+            // one byte tag, then the fields of the variant
+            Container::Enum(variants) => {
+                let sizes = variants
+                    .values()
+                    .map(|(_, fields)| sum(fields.iter().map(|f| bytes(registry, f))));
+                let min = sizes.clone().map(|s| s.0).min().unwrap_or(0);
+                let max = sizes.map(|s| s.1).max().unwrap_or(0);
+                (1 + min, 1 + max)
+            }
+        },
+    }
+}
+
+fn format_json(format: &Format) -> Value {
+    match format {
+        Format::Prim(name) | Format::Named(name) => json!(name),
+        Format::Option(inner) => json!({ "option": format_json(inner) }),
+        Format::Array(inner, n) => json!({ "array": format_json(inner), "len": n }),
+        Format::Tuple(formats) => {
+            json!({ "tuple": formats.iter().map(format_json).collect::<Vec<_>>() })
+        }
+    }
+}
+
+// fields in order, with their offset as long as every field before has a fixed size
+fn fields_json<'a>(
+    registry: &Registry,
+    start: usize,
+    fields: impl Iterator<Item = (&'a str, &'a Format)>,
+) -> Vec<Value> {
+    let mut offset = Some(start);
+    fields
+        .map(|(name, format)| {
+            let (min, max) = bytes(registry, format);
+            let mut field = Map::new();
+            if !name.is_empty() {
+                field.insert("name".into(), json!(name));
+            }
+            field.insert("type".into(), format_json(format));
+            field.insert("bytes".into(), json!([min, max]));
+            if let Some(at) = offset {
+                field.insert("offset".into(), json!(at));
+            }
+            offset = offset.filter(|_| min == max).map(|at| at + max);
+            Value::Object(field)
+        })
+        .collect()
+}
+
+fn container_json(registry: &Registry, name: &'static str) -> Value {
+    let (min, max) = bytes(registry, &Format::Named(name));
+    match &registry[name] {
+        Container::Struct(fields) => json!({
+            "struct": fields_json(registry, 0, fields.iter().map(|(n, f)| (*n, f))),
+            "bytes": [min, max],
+        }),
+        Container::Enum(variants) => {
+            let variants: Vec<_> = variants
+                .iter()
+                .map(|(index, (variant, fields))| {
+                    let (min, max) = sum(fields.iter().map(|f| bytes(registry, f)));
+                    json!({
+                        "name": variant,
+                        "tag": index,
+                        // offsets count from the start of the enum, the tag is byte 0
+                        "fields": fields_json(registry, 1, fields.iter().map(|f| ("", f))),
+                        "bytes": [1 + min, 1 + max],
+                    })
+                })
+                .collect();
+            json!({ "enum": variants, "bytes": [min, max] })
+        }
+    }
+}
+
+// `max_frame_len` leaves room for the auth trailer, which the host always builds with
+fn plain_frame_len<T: MaxEncodedSize>() -> usize {
+    corncobs::max_encoded_len(max_payload_len::<T>() + CRC_LEN)
+}
+
+fn legacy_set_ids() -> Map<String, Value> {
+    sample_actions()
+        .into_iter()
+        .map(|action| {
+            let name = format!("{:?}", action);
+            let name = name.split('(').next().unwrap_or_default().to_string();
+            let (id, _): (Id, Message) = action.into();
+            (name, json!(id))
+        })
+        .collect()
+}
+
+/// The whole protocol description
+pub fn schema() -> Result<Value, TraceError> {
+    let registry = registry()?;
+    let types: Map<_, _> = registry
+        .keys()
+        .map(|name| (name.to_string(), container_json(&registry, name)))
+        .collect();
+    let crc = CKSUM.algorithm;
+    let postcard = wire::BACKEND == "postcard";
+
+    Ok(json!({
+        "protocol_version": PROTOCOL_VERSION,
+        "wire": {
+            "backend": wire::BACKEND,
+            "integers": if postcard {
+                "LEB128 varint, zigzag encoded if signed"
+            } else {
+                "fixed width, little endian"
+            },
+            "bool": "one byte, 0 or 1",
+            "enum": "variant index as one byte, then the fields of the variant",
+            "option": "one byte, 0 for none, 1 followed by the value for some",
+            "array": "elements in order, no length",
+            "struct": "fields in order, no names",
+        },
+        "frame": {
+            "layout": ["seq: u16", "payload", "crc32: u32 little endian"],
+            "seq": "encoded like any other u16, a response echoes the seq of its command",
+            "payload": { "host to device": "Command", "device to host": "Upstream" },
+            "encoding": "COBS, terminated by a 0x00 delimiter",
+            "max_len": {
+                "Command": plain_frame_len::<Command>(),
+                "Upstream": plain_frame_len::<Upstream>(),
+            },
+            "crc": {
+                "name": "CRC-32/CKSUM",
+                "width": crc.width,
+                "poly": format!("{:#010x}", crc.poly),
+                "init": format!("{:#010x}", crc.init),
+                "refin": crc.refin,
+                "refout": crc.refout,
+                "xorout": format!("{:#010x}", crc.xorout),
+                "check": format!("{:#010x}", crc.check),
+                "covers": "seq and payload, the auth trailer if present",
+            },
+            "auth": {
+                "layout": [
+                    "seq: u16",
+                    "payload",
+                    format!("counter: u64 little endian, {} bytes", COUNTER_LEN),
+                    format!("tag: HMAC-SHA256 over counter | seq | payload, first {} bytes", TAG_LEN),
+                    "crc32: u32 little endian",
+                ],
+                "used": "commands to firmware built with the auth feature",
+                "max_len": { "Command": max_frame_len::<Command>() },
+            },
+        },
+        "types": types,
+        "ids": {
+            "get": {
+                "TIME": get::TIME,
+                "TIME_SET": get::TIME_SET,
+                "BLINK_CONFIG": get::BLINK_CONFIG,
+                "RGB_ACTIVE": get::RGB_ACTIVE,
+                "UPTIME": get::UPTIME,
+                "UTC_OFFSET": get::UTC_OFFSET,
+            },
+            "blink_config_params": {
+                "BLINK_START": get::BLINK_START,
+                "BLINK_END": get::BLINK_END,
+                "BLINK_PERIOD": get::BLINK_PERIOD,
+                "BLINK_ACTIVE": get::BLINK_ACTIVE,
+            },
+            "legacy_set": legacy_set_ids(),
+        },
+        "address": {
+            "default": address::DEFAULT,
+            "broadcast": address::BROADCAST,
+        },
+        "capabilities": {
+            "SET_TIME": hello::CAP_SET_TIME,
+            "BLINK_OFF": hello::CAP_BLINK_OFF,
+            "BLINK_NOW": hello::CAP_BLINK_NOW,
+            "BLINK_AT": hello::CAP_BLINK_AT,
+            "SET_RGB": hello::CAP_SET_RGB,
+            "TIME_ZONE": hello::CAP_TIME_ZONE,
+            "GET": hello::CAP_GET,
+            "HELLO": hello::CAP_HELLO,
+            "ACTION": hello::CAP_ACTION,
+            "EVENTS": hello::CAP_EVENTS,
+            "BATCH": hello::CAP_BATCH,
+            "EPOCH_TIME": hello::CAP_EPOCH_TIME,
+        },
+    }))
+}
+
+/// `schema()` as pretty printed JSON, the contents of `docs/schema/<backend>.json`
+pub fn to_json() -> Result<String, TraceError> {
+    let schema = schema()?;
+    let mut json = serde_json::to_string_pretty(&schema).expect("a `Value` always serializes");
+    json.push('\n');
+    Ok(json)
+}
@@ -0,0 +1,67 @@
+//! When to do something, as given on the command line
+//!
+//! Either an RFC 3339 date and time, `2026-10-17T14:30:00Z` in UTC or with
+//! an offset such as `2026-10-17T17:30:00+03:00`, or a time from now written
+//! as `+` and one or more amounts with a unit: `+90s`, `+5m`, `+1h30m`. The
+//! units are `s`, `m`, `h` and `d`.
+
+use std::{str::FromStr, time::Duration};
+
+use shared::date_time::{DateTimeError, UtcDateTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    At(UtcDateTime),
+    In(Duration),
+}
+
+impl When {
+    /// The time this stands for, a relative one counted from `now`
+    pub fn resolve(&self, now: UtcDateTime) -> Result<UtcDateTime, DateTimeError> {
+        match self {
+            When::At(dt) => Ok(*dt),
+            When::In(duration) => now.checked_add(*duration),
+        }
+    }
+}
+
+impl FromStr for When {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.strip_prefix('+') {
+            Some(amounts) => parse_duration(amounts).map(When::In).ok_or_else(|| {
+                format!(
+                    "`{}`: expected a time from now such as +90s, +5m or +1h30m",
+                    s
+                )
+            }),
+            None => s
+                .parse()
+                .map(When::At)
+                .map_err(|e| format!("`{}`: {}", s, e)),
+        }
+    }
+}
+
+// `1h30m`, `None` unless every amount has a unit
+fn parse_duration(mut s: &str) -> Option<Duration> {
+    if s.is_empty() {
+        return None;
+    }
+    let mut total = Duration::ZERO;
+    while !s.is_empty() {
+        let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+        let amount: u64 = s[..digits].parse().ok()?;
+        let unit = match s[digits..].chars().next()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        total = total.checked_add(Duration::from_secs(amount.checked_mul(unit)?))?;
+        s = &s[digits + 1..];
+    }
+    Some(total)
+}
@@ -0,0 +1,292 @@
+//! `Arq` against a simulated device on a lossy in-memory link
+//!
+//! `SimDevice` answers commands the way the firmware does, including the
+//! replay of cached responses to retransmissions, and can be told to lose
+//! requests or responses. Random corruption comes from wrapping it in a
+//! `FaultyPort`. Given a `Verifier` it only accepts authenticated frames.
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use host::{
+    arq::{Arq, ArqConfig, ArqError},
+    fault::FaultyPort,
+};
+use shared::{
+    auth::{Key, Verifier, KEY_LEN},
+    dedup::{self, ResponseCache},
+    deserialize_crc_cobs,
+    event::Event,
+    fault_injector::FaultConfig,
+    frame_accumulator::FrameAccumulator,
+    serialize_crc_cobs, Action, Command, Response, Upstream,
+};
+
+const DEV: u32 = 1;
+const KEY: Key = [0x42; KEY_LEN];
+
+#[derive(Default)]
+struct SimDevice {
+    rx_frames: FrameAccumulator<128>,
+    tx: VecDeque<u8>,
+    responses: ResponseCache,
+    // commands carried out, retransmissions excluded
+    applied: Vec<Command>,
+    // frames that reached the device, retransmissions included
+    received: u32,
+    lose_requests: u32,
+    lose_responses: u32,
+    event_before_response: Option<Event>,
+    verifier: Option<Verifier>,
+}
+
+impl SimDevice {
+    fn reply(&mut self, seq: u16, up: &Upstream) {
+        if self.lose_responses > 0 {
+            self.lose_responses -= 1;
+            return;
+        }
+        let mut buf = [0u8; 128];
+        self.tx
+            .extend(serialize_crc_cobs(seq, up, &mut buf).unwrap());
+    }
+
+    fn handle(&mut self, decoded: Result<(u16, Command), shared::Faults>) {
+        let (seq, cmd) = match decoded {
+            Ok(decoded) => decoded,
+            Err(fault) => return self.reply(0, &Upstream::Response(fault.into())),
+        };
+        self.received += 1;
+
+        let digest = dedup::digest(&cmd);
+        let rsp = match self.responses.replay(seq, digest) {
+            Some(cached) => cached.clone(),
+            None => {
+                let rsp = match &cmd {
+                    Command::Get(id, param, dev) => Response::Data(*id, *param, 42, *dev),
+                    _ => Response::SetOk,
+                };
+                self.applied.push(cmd);
+                self.responses.store(seq, digest, rsp.clone());
+                rsp
+            }
+        };
+
+        if let Some(event) = self.event_before_response.take() {
+            self.reply(0, &Upstream::Event(event, DEV));
+        }
+        self.reply(seq, &Upstream::Response(rsp));
+    }
+}
+
+impl Write for SimDevice {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.lose_requests > 0 {
+            self.lose_requests -= 1;
+            return Ok(buf.len());
+        }
+        for &b in buf {
+            if let Some(frame) = self.rx_frames.push(b) {
+                let decoded = match &mut self.verifier {
+                    Some(verifier) => verifier.deserialize_crc_cobs(frame),
+                    None => deserialize_crc_cobs(frame),
+                };
+                self.handle(decoded);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for SimDevice {
+    // behaves like a serial port with a short read timeout
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.tx.is_empty() {
+            sleep(Duration::from_millis(1));
+            return Err(io::Error::new(ErrorKind::TimedOut, "no data"));
+        }
+        let n = buf.len().min(self.tx.len());
+        for (dst, src) in buf.iter_mut().zip(self.tx.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+fn fast(retries: u32) -> ArqConfig {
+    ArqConfig {
+        retries,
+        timeout: Duration::from_millis(5),
+        max_timeout: Duration::from_millis(20),
+        deadline: None,
+    }
+}
+
+fn blink(n: u32) -> Command {
+    Command::Action(Action::BlinkNow(n, 1), DEV)
+}
+
+#[test]
+fn lost_requests_are_resent() {
+    let device = SimDevice {
+        lose_requests: 2,
+        ..Default::default()
+    };
+    let mut arq = Arq::new(device, fast(5));
+
+    assert_eq!(arq.request(&blink(1), |_, _| {}).unwrap(), Response::SetOk);
+    assert_eq!(arq.stats().resends, 2);
+    assert_eq!(arq.stats().timeouts, 2);
+    assert_eq!(arq.link().applied, [blink(1)]);
+}
+
+#[test]
+fn lost_responses_are_replayed_not_reapplied() {
+    let device = SimDevice {
+        lose_responses: 2,
+        ..Default::default()
+    };
+    let mut arq = Arq::new(device, fast(5));
+
+    assert_eq!(arq.request(&blink(1), |_, _| {}).unwrap(), Response::SetOk);
+    assert_eq!(arq.link().received, 3);
+    assert_eq!(arq.link().applied, [blink(1)]);
+}
+
+#[test]
+fn gives_up_after_the_last_retry() {
+    let device = SimDevice {
+        lose_requests: u32::MAX,
+        ..Default::default()
+    };
+    let mut arq = Arq::new(device, fast(3));
+
+    let err = arq.request(&blink(1), |_, _| {}).unwrap_err();
+    assert!(
+        matches!(err, ArqError::NoResponse { attempts: 4 }),
+        "{:?}",
+        err
+    );
+    assert_eq!(arq.stats().resends, 3);
+    assert_eq!(io::Error::from(err).kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn deadline_ends_the_retries() {
+    let device = SimDevice {
+        lose_requests: u32::MAX,
+        ..Default::default()
+    };
+    let config = ArqConfig {
+        deadline: Some(Duration::from_millis(50)),
+        ..fast(u32::MAX)
+    };
+    let mut arq = Arq::new(device, config);
+
+    let start = Instant::now();
+    let err = arq.request(&blink(1), |_, _| {}).unwrap_err();
+    assert!(matches!(err, ArqError::Deadline { .. }), "{:?}", err);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn broadcasts_are_not_requests() {
+    let mut arq = Arq::new(SimDevice::default(), fast(3));
+    let cmd = Command::Action(Action::BlinkOff, shared::address::BROADCAST);
+
+    assert!(matches!(
+        arq.request(&cmd, |_, _| {}),
+        Err(ArqError::Broadcast)
+    ));
+    arq.send(&cmd).unwrap();
+    assert_eq!(arq.link().applied, [cmd]);
+}
+
+#[test]
+fn events_are_handed_over_while_waiting() {
+    let device = SimDevice {
+        event_before_response: Some(Event::Rebooted),
+        ..Default::default()
+    };
+    let mut arq = Arq::new(device, fast(0));
+
+    let mut events = Vec::new();
+    let rsp = arq.request(&Command::Get(1, 0, DEV), |event, dev| {
+        events.push((event, dev))
+    });
+    assert_eq!(rsp.unwrap(), Response::Data(1, 0, 42, DEV));
+    assert_eq!(events, [(Event::Rebooted, DEV)]);
+}
+
+#[test]
+fn authenticated_resends_are_accepted() {
+    let device = SimDevice {
+        lose_responses: 2,
+        verifier: Some(Verifier::new(KEY)),
+        ..Default::default()
+    };
+    let mut arq = Arq::new(device, fast(5));
+    arq.set_key(KEY);
+
+    assert_eq!(arq.request(&blink(1), |_, _| {}).unwrap(), Response::SetOk);
+    assert_eq!(arq.request(&blink(2), |_, _| {}).unwrap(), Response::SetOk);
+    assert_eq!(arq.link().received, 4);
+    assert_eq!(arq.link().applied, [blink(1), blink(2)]);
+}
+
+#[test]
+fn auth_failures_are_not_retried() {
+    for key in [None, Some([0x24; KEY_LEN])] {
+        let device = SimDevice {
+            verifier: Some(Verifier::new(KEY)),
+            ..Default::default()
+        };
+        let mut arq = Arq::new(device, fast(5));
+        if let Some(key) = key {
+            arq.set_key(key);
+        }
+
+        let err = arq.request(&blink(1), |_, _| {}).unwrap_err();
+        assert!(matches!(err, ArqError::AuthFailed), "{:?}", err);
+        assert_eq!(arq.stats().resends, 0);
+        assert!(arq.link().applied.is_empty());
+    }
+}
+
+#[test]
+fn exactly_once_over_a_noisy_link() {
+    let config = FaultConfig {
+        bit_flip: 0.2,
+        drop_byte: 0.1,
+        truncate: 0.1,
+        duplicate: 0.2,
+        garbage: 0.2,
+    };
+    // responses are corrupted per byte, so at much lower rates
+    let rx_config = FaultConfig {
+        bit_flip: 0.01,
+        drop_byte: 0.005,
+        ..Default::default()
+    };
+    let port = FaultyPort::new(
+        FaultyPort::both_ways(SimDevice::default(), 11, rx_config),
+        7,
+        config,
+    );
+    let mut arq = Arq::new(port, fast(30));
+
+    let sent: Vec<_> = (0..100).map(blink).collect();
+    for cmd in &sent {
+        assert_eq!(arq.request(cmd, |_, _| {}).unwrap(), Response::SetOk);
+    }
+
+    assert_eq!(arq.link().inner().inner().applied, sent);
+    let stats = arq.stats();
+    assert!(stats.resends > 0 && stats.stale > 0, "{:?}", stats);
+}
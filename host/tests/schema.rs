@@ -0,0 +1,92 @@
+//! The protocol schema against the Rust types and `docs/schema/`
+//!
+//! A failure here after changing a wire type means the schema files are out
+//! of date, regenerate them as described in `src/bin/schema.rs`.
+
+use std::fs;
+
+use host::schema::{self, bytes, variant_names, Container, Format, Registry};
+use serde::Deserialize;
+use shared::{
+    date_time::UtcDateTime,
+    encoded_size::MaxEncodedSize,
+    error::ErrorCode,
+    event::Event,
+    hello::HelloInfo,
+    time_zone::{DstRule, TimeZone, Transition},
+    wire, Action, Command, Message, Response, Upstream,
+};
+
+#[test]
+fn schema_matches_the_docs() {
+    let path = format!(
+        "{}/../docs/schema/{}.json",
+        env!("CARGO_MANIFEST_DIR"),
+        wire::BACKEND
+    );
+    let docs = fs::read_to_string(&path).unwrap();
+    assert!(
+        schema::to_json().unwrap() == docs,
+        "{} is out of date, regenerate it with `cargo run --bin schema`",
+        path
+    );
+}
+
+fn assert_all_traced<T: for<'de> Deserialize<'de>>(registry: &Registry, name: &str) {
+    let Some(Container::Enum(variants)) = registry.get(name) else {
+        panic!("{} was not traced as an enum", name);
+    };
+    let traced: Vec<_> = variants.values().map(|(variant, _)| *variant).collect();
+    assert_eq!(
+        traced,
+        variant_names::<T>().unwrap(),
+        "add the missing {} variants to the samples in `host::schema`",
+        name
+    );
+}
+
+#[test]
+fn every_variant_is_traced() {
+    let registry = schema::registry().unwrap();
+    assert_all_traced::<Command>(&registry, "Command");
+    assert_all_traced::<Message>(&registry, "Message");
+    assert_all_traced::<Action>(&registry, "Action");
+    assert_all_traced::<Upstream>(&registry, "Upstream");
+    assert_all_traced::<Response>(&registry, "Response");
+    assert_all_traced::<ErrorCode>(&registry, "ErrorCode");
+    assert_all_traced::<Event>(&registry, "Event");
+    assert!(variant_names::<UtcDateTime>().is_none());
+}
+
+fn assert_max<T: MaxEncodedSize>(registry: &Registry, name: &'static str) {
+    let (_, max) = bytes(registry, &Format::Named(name));
+    assert_eq!(max, T::MAX_ENCODED_SIZE, "{}", name);
+}
+
+// the traced layout and the hand written bounds of `encoded_size` must agree
+#[test]
+fn sizes_agree_with_max_encoded_size() {
+    let registry = schema::registry().unwrap();
+    assert_max::<Command>(&registry, "Command");
+    assert_max::<Message>(&registry, "Message");
+    assert_max::<Action>(&registry, "Action");
+    assert_max::<Upstream>(&registry, "Upstream");
+    assert_max::<Response>(&registry, "Response");
+    assert_max::<ErrorCode>(&registry, "ErrorCode");
+    assert_max::<Event>(&registry, "Event");
+    assert_max::<UtcDateTime>(&registry, "UtcDateTime");
+    assert_max::<HelloInfo>(&registry, "HelloInfo");
+    assert_max::<TimeZone>(&registry, "TimeZone");
+    assert_max::<DstRule>(&registry, "DstRule");
+    assert_max::<Transition>(&registry, "Transition");
+}
+
+#[test]
+fn none_cannot_be_traced() {
+    let mut registry = Registry::new();
+    assert!(schema::trace(&mut registry, &None::<u32>).is_err());
+    assert_eq!(
+        schema::trace(&mut registry, &Some(1u32)).unwrap(),
+        Format::Option(Box::new(Format::Prim("u32")))
+    );
+}
@@ -0,0 +1,101 @@
+//! Blink times from the command line, absolute and relative, down to the
+//! `Message::D` an older device receives
+
+use std::time::Duration;
+
+use host::when::When;
+use shared::{date_time::UtcDateTime, Action, Id, Message};
+
+fn utc(s: &str) -> UtcDateTime {
+    s.parse().unwrap()
+}
+
+fn when(s: &str) -> When {
+    s.parse().unwrap()
+}
+
+// `Message::D` of a blink at `when`, seen from `now`
+fn schedule(when: &str, now: &str) -> Message {
+    let at = self::when(when).resolve(utc(now)).unwrap();
+    let (id, msg): (Id, Message) = Action::BlinkAt(at, 10, 6).into();
+    assert_eq!(id, 4);
+    msg
+}
+
+#[test]
+fn relative_times_roll_over() {
+    let now = "2026-10-17T14:58:45Z";
+    assert_eq!(
+        schedule("+90s", now),
+        Message::D(utc("2026-10-17T15:00:15Z"), 10, 6)
+    );
+    assert_eq!(
+        schedule("+5m", now),
+        Message::D(utc("2026-10-17T15:03:45Z"), 10, 6)
+    );
+    assert_eq!(
+        schedule("+1h30m", now),
+        Message::D(utc("2026-10-17T16:28:45Z"), 10, 6)
+    );
+
+    // the end of a day, a month and a leap year February
+    let now = "2024-02-28T23:59:30Z";
+    assert_eq!(
+        schedule("+45s", now),
+        Message::D(utc("2024-02-29T00:00:15Z"), 10, 6)
+    );
+    assert_eq!(
+        schedule("+1d", now),
+        Message::D(utc("2024-02-29T23:59:30Z"), 10, 6)
+    );
+    assert_eq!(
+        schedule("+2d", now),
+        Message::D(utc("2024-03-01T23:59:30Z"), 10, 6)
+    );
+    let now = "2026-12-31T23:59:59Z";
+    assert_eq!(
+        schedule("+1s", now),
+        Message::D(utc("2027-01-01T00:00:00Z"), 10, 6)
+    );
+}
+
+#[test]
+fn absolute_times_are_utc() {
+    let now = "2026-10-17T12:00:00Z";
+    let expected = Message::D(utc("2026-10-17T14:30:00Z"), 10, 6);
+    assert_eq!(schedule("2026-10-17T14:30:00Z", now), expected);
+    assert_eq!(schedule("2026-10-17T17:30:00+03:00", now), expected);
+    assert_eq!(schedule("2026-10-17T09:30:00-05:00", now), expected);
+
+    // an offset that moves the time to the previous day
+    assert_eq!(
+        schedule("2026-10-18T01:00:00+02:00", now),
+        Message::D(utc("2026-10-17T23:00:00Z"), 10, 6)
+    );
+}
+
+#[test]
+fn parsing() {
+    assert_eq!(when("+90s"), When::In(Duration::from_secs(90)));
+    assert_eq!(when("+1d2h3m4s"), When::In(Duration::from_secs(93_784)));
+    assert_eq!(
+        when("2026-10-17T14:30:00Z"),
+        When::At(utc("2026-10-17T14:30:00Z"))
+    );
+
+    for bad in [
+        "",
+        "+",
+        "+90",
+        "+s",
+        "+5x",
+        "+1h30",
+        "90s",
+        "2026-10-17",
+        "2026-02-30T00:00:00Z",
+    ] {
+        assert!(bad.parse::<When>().is_err(), "{}", bad);
+    }
+    let err = "2026-02-30T00:00:00Z".parse::<When>().unwrap_err();
+    assert!(err.contains("day 30"), "{}", err);
+}
@@ -0,0 +1,118 @@
+//! Encoder benchmarks, `cargo bench`
+//!
+//! Compares the in place and streaming encoders against the previous
+//! implementation, which serialized into the output buffer, copied it and
+//! COBS-encoded the copy back.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use shared::{
+    date_time::UtcDateTime,
+    encoder::serialize_crc_cobs_to,
+    hello::{self, HelloInfo},
+    serialize_crc_cobs, Action, Command, Faults, Response, Seq, CKSUM,
+};
+
+const BUF: usize = 128;
+
+// the encoder before the copy was removed
+fn serialize_crc_cobs_copy<'a, T: serde::Serialize, const N: usize>(
+    seq: Seq,
+    t: &T,
+    out_buf: &'a mut [u8; N],
+) -> Result<&'a [u8], Faults> {
+    let n_ser = ssmarshal::serialize(out_buf, &(seq, t)).map_err(|_| Faults::BufferTooSmall)?;
+    let crc = CKSUM.checksum(&out_buf[0..n_ser]);
+    let n_crc =
+        ssmarshal::serialize(&mut out_buf[n_ser..], &crc).map_err(|_| Faults::BufferTooSmall)?;
+    if corncobs::max_encoded_len(n_ser + n_crc) > N {
+        return Err(Faults::BufferTooSmall);
+    }
+    let buf_copy = *out_buf;
+    let n = corncobs::encode_buf(&buf_copy[0..n_ser + n_crc], out_buf);
+    Ok(&out_buf[0..n])
+}
+
+// stand-in for the firmware's `UartTx`, every variant ends up writing here
+struct Uart {
+    buf: [u8; BUF],
+    n: usize,
+}
+
+impl Uart {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        self.buf[self.n..self.n + bytes.len()].copy_from_slice(bytes);
+        self.n += bytes.len();
+        Ok(())
+    }
+}
+
+fn bench_value<T: serde::Serialize>(c: &mut Criterion, name: &str, value: &T) {
+    let mut group = c.benchmark_group(name);
+    let mut uart = Uart {
+        buf: [0; BUF],
+        n: 0,
+    };
+
+    group.bench_function("copy", |b| {
+        let mut buf = [0u8; BUF];
+        b.iter(|| {
+            uart.n = 0;
+            let frame = serialize_crc_cobs_copy(black_box(1), black_box(value), &mut buf).unwrap();
+            uart.write_bytes(frame)
+        })
+    });
+
+    group.bench_function("in_place", |b| {
+        let mut buf = [0u8; BUF];
+        b.iter(|| {
+            uart.n = 0;
+            let frame = serialize_crc_cobs(black_box(1), black_box(value), &mut buf).unwrap();
+            uart.write_bytes(frame)
+        })
+    });
+
+    group.bench_function("streaming", |b| {
+        let mut scratch = [0u8; BUF];
+        b.iter(|| {
+            uart.n = 0;
+            serialize_crc_cobs_to(
+                black_box(1),
+                black_box(value),
+                &mut scratch,
+                |bytes: &[u8]| uart.write_bytes(bytes),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let dt = UtcDateTime {
+        year: 2023,
+        month: 10,
+        day: 17,
+        hour: 14,
+        minute: 30,
+        second: 0,
+        nanoseconds: 0,
+    };
+    bench_value(
+        c,
+        "command",
+        &Command::Action(Action::BlinkAt(dt, 10, 3), 1),
+    );
+
+    let info = HelloInfo {
+        protocol_version: hello::PROTOCOL_VERSION,
+        fw_version: hello::padded("0.1.0"),
+        git_hash: hello::padded("0123abcd"),
+        dev_id: 1,
+        capabilities: hello::CAP_HELLO,
+    };
+    bench_value(c, "response", &Response::Hello(info));
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+//! Feed arbitrary bytes into the decoder
+//!
+//! Run from `shared` with `cargo +nightly fuzz run -O decode`. Malformed input
+//! may only ever come back as a `Faults` value, any panic or out of bounds
+//! access is reported as a crash by libFuzzer.
+//!
+//! `-O` matters: without it cargo-fuzz enables debug assertions for every
+//! crate, and ssmarshal reports truncated input through a `debug_assert!`
+//! before returning its error. The host and firmware builds switch those off
+//! for ssmarshal (see `[profile.dev.package.ssmarshal]`), `-O` does the same.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shared::{
+    deserialize_crc_cobs, encoded_size::max_frame_len, frame_accumulator::FrameAccumulator,
+    Command, Faults, Seq, Upstream,
+};
+
+fn decode<T>(frame: &mut [u8]) -> Result<(Seq, T), Faults>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    deserialize_crc_cobs::<T>(frame)
+}
+
+fuzz_target!(|data: &[u8]| {
+    // the whole input as a single frame, as the decoder sees it
+    let _ = decode::<Command>(&mut data.to_vec());
+    let _ = decode::<Upstream>(&mut data.to_vec());
+
+    // the input as a byte stream, split into frames the way the device does,
+    // with the firmware's buffer so that the widest commands get through
+    let mut acc = FrameAccumulator::<{ max_frame_len::<Command>() }>::new();
+    for &b in data {
+        if let Some(frame) = acc.push(b) {
+            let _ = decode::<Command>(frame);
+        }
+    }
+});
@@ -0,0 +1,103 @@
+//! Device addresses for several devices sharing one bus
+//!
+//! Every `Command` carries the `DevId` of the device it is meant for. A device
+//! ignores frames addressed to others, and acts on `BROADCAST` frames without
+//! answering so that responses from several devices cannot collide.
+
+use crate::{Command, DevId};
+
+/// Address of every device on the bus, never answered
+pub const BROADCAST: DevId = DevId::MAX;
+
+/// Address used when none is configured
+pub const DEFAULT: DevId = 0b001;
+
+impl Command {
+    /// Address the command is sent to
+    pub fn dev_id(&self) -> DevId {
+        match self {
+            Command::Set(_, _, dev_id)
+            | Command::Get(_, _, dev_id)
+            | Command::Hello(dev_id)
+            | Command::Action(_, dev_id)
+            | Command::Batch(_, dev_id) => *dev_id,
+        }
+    }
+
+    /// true if the device at `dev_id` has to carry out the command
+    pub fn addressed_to(&self, dev_id: DevId) -> bool {
+        let to = self.dev_id();
+        to == dev_id || to == BROADCAST
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.dev_id() == BROADCAST
+    }
+}
+
+/// Parse a device address, decimal or `0x`/`0b` prefixed
+///
+/// `const` so that the firmware can take its address from a build time
+/// environment variable. `BROADCAST` is not a valid device address.
+pub const fn parse(s: &str) -> Option<DevId> {
+    let bytes = s.as_bytes();
+    let (radix, mut i) = match bytes {
+        [b'0', b'x' | b'X', ..] => (16, 2),
+        [b'0', b'b' | b'B', ..] => (2, 2),
+        _ => (10, 0),
+    };
+    if i == bytes.len() {
+        return None;
+    }
+
+    let mut value: DevId = 0;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b @ b'0'..=b'9' => (b - b'0') as DevId,
+            b @ b'a'..=b'f' => (b - b'a' + 10) as DevId,
+            b @ b'A'..=b'F' => (b - b'A' + 10) as DevId,
+            _ => return None,
+        };
+        if digit >= radix {
+            return None;
+        }
+        value = match value.checked_mul(radix) {
+            Some(v) => match v.checked_add(digit) {
+                Some(v) => v,
+                None => return None,
+            },
+            None => return None,
+        };
+        i += 1;
+    }
+
+    if value == BROADCAST {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[test]
+fn parse_addresses() {
+    assert_eq!(parse("1"), Some(1));
+    assert_eq!(parse("0x1f"), Some(31));
+    assert_eq!(parse("0b101"), Some(5));
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("0x"), None);
+    assert_eq!(parse("0b102"), None);
+    assert_eq!(parse("12a"), None);
+    assert_eq!(parse("4294967296"), None);
+    assert_eq!(parse("0xffffffff"), None);
+}
+
+#[test]
+fn broadcast_reaches_every_device() {
+    let to_two = Command::Hello(2);
+    assert!(to_two.addressed_to(2));
+    assert!(!to_two.addressed_to(1));
+
+    let to_all = Command::Action(crate::Action::BlinkOff, BROADCAST);
+    assert!(to_all.is_broadcast());
+    assert!(to_all.addressed_to(1) && to_all.addressed_to(2));
+}
@@ -0,0 +1,263 @@
+//! Authenticated frames, pre-shared key
+//!
+//! The CRC only tells accidents apart from intact frames, anyone able to
+//! write to the UART can compute it. An authenticated frame carries a counter
+//! and a MAC between payload and CRC:
+//!
+//! `seq | payload | counter | tag | crc(seq | payload | counter | tag)`
+//!
+//! `counter` is a little endian `u64` that the sender increases with every
+//! frame, resends included. `tag` is HMAC-SHA256 over `counter | seq | payload`
+//! under the shared key, truncated to `TAG_LEN` bytes. A `Verifier` accepts a
+//! frame only if the tag matches and the counter is above that of the last
+//! frame it accepted, so a recorded frame cannot be played back later.
+//!
+//! The CRC is checked first, frames damaged in transit are still reported as
+//! `Faults::BitFlipData` and resent. Everything else that fails the checks
+//! is `Faults::AuthFailed`.
+//!
+//! The `Verifier` keeps the last counter in RAM, after a reboot of the device
+//! any counter is new to it. The host starts its counter from the current
+//! time to stay ahead of what the device has seen before.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{encoder::encode_in_place_sealed, wire, Faults, Seq, CKSUM, CRC_LEN};
+
+/// Bytes of the pre-shared key
+pub const KEY_LEN: usize = 32;
+/// Bytes of the truncated HMAC
+pub const TAG_LEN: usize = 16;
+/// Bytes of the counter
+pub const COUNTER_LEN: usize = 8;
+/// Bytes an authenticated frame carries on top of a plain one, before COBS
+pub const AUTH_LEN: usize = COUNTER_LEN + TAG_LEN;
+
+pub type Key = [u8; KEY_LEN];
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key from its `2 * KEY_LEN` hex digits, `None` for anything else
+///
+/// `const` so that the firmware can check the key provisioned at build time.
+pub const fn parse_key(hex: &str) -> Option<Key> {
+    let hex = hex.as_bytes();
+    if hex.len() != 2 * KEY_LEN {
+        return None;
+    }
+    let mut key = [0; KEY_LEN];
+    let mut i = 0;
+    while i < KEY_LEN {
+        match (digit(hex[2 * i]), digit(hex[2 * i + 1])) {
+            (Some(hi), Some(lo)) => key[i] = hi << 4 | lo,
+            _ => return None,
+        }
+        i += 1;
+    }
+    Some(key)
+}
+
+const fn digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn mac(key: &Key, counter: &[u8], payload: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(counter);
+    mac.update(payload);
+    mac
+}
+
+/// Sending end, signs every frame under the next counter
+pub struct Signer {
+    key: Key,
+    counter: u64,
+}
+
+impl Signer {
+    /// `counter` is taken as used, the first frame goes out with `counter + 1`
+    pub const fn new(key: Key, counter: u64) -> Self {
+        Signer { key, counter }
+    }
+
+    /// Counter of the last signed frame
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// `serialize_crc_cobs` for an authenticated frame
+    pub fn serialize_crc_cobs<'a, T: serde::Serialize>(
+        &mut self,
+        seq: Seq,
+        t: &T,
+        out_buf: &'a mut [u8],
+    ) -> Result<&'a [u8], Faults> {
+        let n = self.encode_in_place(seq, t, out_buf)?;
+        Ok(&out_buf[..n])
+    }
+
+    pub(crate) fn encode_in_place<T: serde::Serialize>(
+        &mut self,
+        seq: Seq,
+        t: &T,
+        buf: &mut [u8],
+    ) -> Result<usize, Faults> {
+        let counter = self
+            .counter
+            .checked_add(1)
+            .ok_or(Faults::AuthFailed)?
+            .to_le_bytes();
+        let n = encode_in_place_sealed(seq, t, buf, AUTH_LEN, |payload, trailer| {
+            let tag = mac(&self.key, &counter, payload).finalize().into_bytes();
+            trailer[..COUNTER_LEN].copy_from_slice(&counter);
+            trailer[COUNTER_LEN..].copy_from_slice(&tag[..TAG_LEN]);
+        })?;
+        self.counter += 1;
+        Ok(n)
+    }
+}
+
+/// Receiving end, accepts each counter at most once and in increasing order
+pub struct Verifier {
+    key: Key,
+    last: u64,
+}
+
+impl Verifier {
+    pub const fn new(key: Key) -> Self {
+        Verifier { key, last: 0 }
+    }
+
+    /// Counter of the last accepted frame, 0 before the first
+    pub fn last_counter(&self) -> u64 {
+        self.last
+    }
+
+    /// `deserialize_crc_cobs` for an authenticated frame
+    pub fn deserialize_crc_cobs<T>(&mut self, in_buf: &mut [u8]) -> Result<(Seq, T), Faults>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        let n = corncobs::decode_in_place(in_buf)?;
+        if n < CRC_LEN {
+            return Err(Faults::Truncated);
+        }
+        let (signed, crc) = in_buf[..n].split_at(n - CRC_LEN);
+        if CKSUM.checksum(signed).to_le_bytes() != crc {
+            return Err(Faults::BitFlipData);
+        }
+        // intact but too short to be signed, a plain frame
+        if signed.len() < AUTH_LEN {
+            return Err(Faults::AuthFailed);
+        }
+
+        let (payload, trailer) = signed.split_at(signed.len() - AUTH_LEN);
+        let (counter, tag) = trailer.split_at(COUNTER_LEN);
+        mac(&self.key, counter, payload)
+            .verify_truncated_left(tag)
+            .map_err(|_| Faults::AuthFailed)?;
+        let mut bytes = [0; COUNTER_LEN];
+        bytes.copy_from_slice(counter);
+        let counter = u64::from_le_bytes(bytes);
+        if counter <= self.last {
+            return Err(Faults::AuthFailed);
+        }
+
+        let ((seq, t), used) = wire::deserialize::<(Seq, T)>(payload)?;
+        if used != payload.len() {
+            return Err(Faults::Deserialize);
+        }
+        self.last = counter;
+        Ok((seq, t))
+    }
+}
+
+#[cfg(test)]
+const KEY: Key = [0x5a; KEY_LEN];
+
+#[test]
+fn signed_frames_verify_once() {
+    let mut signer = Signer::new(KEY, 41);
+    let mut verifier = Verifier::new(KEY);
+    let mut buf = [0u8; 64];
+
+    let frame = signer.serialize_crc_cobs(7, &1234u32, &mut buf).unwrap();
+    let mut recorded = frame.to_vec();
+    let mut rx = frame.to_vec();
+    assert_eq!(verifier.deserialize_crc_cobs(&mut rx), Ok((7, 1234u32)));
+    assert_eq!(verifier.last_counter(), 42);
+
+    // played back, or replaced by an older frame
+    let err = verifier.deserialize_crc_cobs::<u32>(&mut recorded);
+    assert_eq!(err, Err(Faults::AuthFailed));
+    let mut older = Signer::new(KEY, 10);
+    let mut rx = older
+        .serialize_crc_cobs(8, &1u32, &mut buf)
+        .unwrap()
+        .to_vec();
+    let err = verifier.deserialize_crc_cobs::<u32>(&mut rx);
+    assert_eq!(err, Err(Faults::AuthFailed));
+
+    // the same payload under the next counter is fine
+    let mut rx = signer
+        .serialize_crc_cobs(7, &1234u32, &mut buf)
+        .unwrap()
+        .to_vec();
+    assert_eq!(verifier.deserialize_crc_cobs(&mut rx), Ok((7, 1234u32)));
+}
+
+#[test]
+fn wrong_key_and_plain_frames_fail() {
+    let mut verifier = Verifier::new(KEY);
+    let mut buf = [0u8; 64];
+
+    let mut other = Signer::new([0xa5; KEY_LEN], 0);
+    let mut rx = other
+        .serialize_crc_cobs(1, &5u32, &mut buf)
+        .unwrap()
+        .to_vec();
+    let err = verifier.deserialize_crc_cobs::<u32>(&mut rx);
+    assert_eq!(err, Err(Faults::AuthFailed));
+
+    // a valid plain frame with room for a trailer, the CRC matches but nothing else
+    let mut rx = crate::serialize_crc_cobs(1, &[5u8; 32], &mut buf)
+        .unwrap()
+        .to_vec();
+    let err = verifier.deserialize_crc_cobs::<[u8; 32]>(&mut rx);
+    assert_eq!(err, Err(Faults::AuthFailed));
+    assert_eq!(verifier.last_counter(), 0);
+}
+
+#[test]
+fn bit_flips_are_not_auth_failures() {
+    let mut signer = Signer::new(KEY, 0);
+    let mut verifier = Verifier::new(KEY);
+    let mut buf = [0u8; 64];
+
+    let frame = signer.serialize_crc_cobs(1, &5u32, &mut buf).unwrap();
+    for i in 0..frame.len() - 1 {
+        let mut rx = frame.to_vec();
+        rx[i] ^= 0x10;
+        if rx[i] == 0 {
+            continue;
+        }
+        let err = verifier.deserialize_crc_cobs::<u32>(&mut rx).unwrap_err();
+        assert_ne!(err, Faults::AuthFailed, "flip in byte {i}");
+    }
+}
+
+#[test]
+fn keys_parse_from_hex() {
+    let hex = "000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F";
+    let key = parse_key(hex).unwrap();
+    assert_eq!(key[..4], [0, 1, 2, 3]);
+    assert_eq!(key[31], 0x1f);
+    assert_eq!(parse_key(&hex[1..]), None);
+    assert_eq!(parse_key(&hex.replace('0', "g")), None);
+}
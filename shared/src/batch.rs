@@ -0,0 +1,92 @@
+//! Several actions applied as one, see `Command::Batch`
+//!
+//! The device checks every action of a batch before applying any of them,
+//! then applies them all under a single lock, so the timer task never sees a
+//! half applied configuration. A rejected batch leaves the device untouched
+//! and is answered with `Response::BatchRejected`.
+
+use crate::{
+    error::{check_action, Detail, ErrorCode},
+    Action,
+};
+
+/// Most actions in one batch
+pub const BATCH_LEN: usize = 4;
+
+/// Actions applied in order, `None` slots are skipped
+pub type Batch = [Option<Action>; BATCH_LEN];
+
+/// Fill a batch from `actions`, `None` if there are more than `BATCH_LEN`
+pub fn from_actions(actions: &[Action]) -> Option<Batch> {
+    if actions.len() > BATCH_LEN {
+        return None;
+    }
+    let mut batch: Batch = Default::default();
+    for (slot, action) in batch.iter_mut().zip(actions) {
+        *slot = Some(action.clone());
+    }
+    Some(batch)
+}
+
+/// Check `actions` in the order the device applies them
+///
+/// `time_set` is the device state before the batch, a `SetTime` counts for
+/// the actions after it. On rejection returns the index of the first bad
+/// action with the reason.
+pub fn validate(
+    actions: &[Option<Action>],
+    mut time_set: bool,
+) -> Result<(), (u8, ErrorCode, Detail)> {
+    for (index, action) in actions.iter().enumerate() {
+        let Some(action) = action else { continue };
+        check_action(action, time_set).map_err(|(code, detail)| (index as u8, code, detail))?;
+        if let Action::SetTime(_) | Action::SetTimeEpoch(_) = action {
+            time_set = true;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+const DT: crate::date_time::UtcDateTime = crate::date_time::UtcDateTime {
+    year: 2023,
+    month: 10,
+    day: 17,
+    hour: 14,
+    minute: 30,
+    second: 0,
+    nanoseconds: 0,
+};
+
+#[test]
+fn set_time_counts_for_later_actions() {
+    let batch = from_actions(&[Action::SetTime(DT), Action::BlinkAt(DT, 10, 3)]).unwrap();
+    assert_eq!(validate(&batch, false), Ok(()));
+
+    // the other way round the blink comes before the time is known
+    let batch = from_actions(&[Action::BlinkAt(DT, 10, 3), Action::SetTime(DT)]).unwrap();
+    assert_eq!(validate(&batch, false), Err((0, ErrorCode::TimeNotSet, 0)));
+    assert_eq!(validate(&batch, true), Ok(()));
+}
+
+#[test]
+fn reports_the_first_rejected_index() {
+    let batch = [
+        Some(Action::SetRgb(true)),
+        None,
+        Some(Action::BlinkNow(10, 500)),
+        Some(Action::BlinkNow(0, 3)),
+    ];
+    assert_eq!(
+        validate(&batch, true),
+        Err((2, ErrorCode::FrequencyOutOfRange, 500))
+    );
+}
+
+#[test]
+fn batch_holds_at_most_batch_len_actions() {
+    assert_eq!(from_actions(&[]), Some([None, None, None, None]));
+    assert_eq!(validate(&from_actions(&[]).unwrap(), false), Ok(()));
+    let too_many = [(); BATCH_LEN + 1].map(|_| Action::BlinkOff);
+    assert_eq!(from_actions(&too_many), None);
+}
@@ -1,7 +1,37 @@
-use chrono::{Datelike, TimeZone, Timelike, Utc};
+//! Calendar date and time as sent on the wire
+//!
+//! `UtcDateTime` is plain fields, any combination can arrive in a frame.
+//! `validate` checks them, so the firmware can reject a bad date before
+//! using it. Conversion to and from epoch milliseconds or nanoseconds and
+//! adding or subtracting a `Duration` need no chrono either, the firmware
+//! keeps its clock as epoch milliseconds and converts with these.
+//!
+//! With the `chrono` feature `UtcDateTime` also converts to and from
+//! `chrono::DateTime<Utc>`. Converting to chrono is fallible for the same
+//! reason as `validate`, converting from chrono never is and keeps the
+//! nanoseconds.
+//!
+//! With the `std` feature `UtcDateTime` is written and parsed as RFC 3339,
+//! `2026-10-17T14:30:00Z`. Parsing takes any offset from UTC and converts,
+//! `Display` always writes UTC.
+
+#[cfg(feature = "std")]
+use core::str::FromStr;
+use core::{
+    fmt,
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Date and time in UTC
+///
+/// Ordered field by field, year first, which is chronological for valid
+/// values, leap seconds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct UtcDateTime {
     pub year: i32,
     pub month: u32,
@@ -12,8 +42,497 @@ pub struct UtcDateTime {
     pub nanoseconds: u32,
 }
 
-impl From<chrono::DateTime<Utc>> for UtcDateTime {
-    fn from(dt: chrono::DateTime<Utc>) -> Self {
+/// Nanoseconds since 1970-01-01T00:00:00Z, leap seconds not counted
+///
+/// The compact wire form of a `UtcDateTime`, one `i64` instead of seven
+/// fields, for the years 1677 to 2262. See `UtcDateTime::to_epoch_nanos`
+/// and `from_epoch_nanos`.
+pub type EpochNanos = i64;
+
+/// Milliseconds of an `EpochNanos`, rounded down like `to_epoch_millis`
+pub const fn epoch_nanos_to_millis(nanos: EpochNanos) -> i64 {
+    nanos.div_euclid(1_000_000)
+}
+
+/// Earliest year chrono can represent
+pub const MIN_YEAR: i32 = -262_143;
+/// Latest year chrono can represent
+pub const MAX_YEAR: i32 = 262_142;
+
+/// The field of a `UtcDateTime` that is out of range, with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeError {
+    /// outside `MIN_YEAR..=MAX_YEAR`
+    Year(i32),
+    /// outside `1..=12`
+    Month(u32),
+    /// outside `1..=days_in_month`
+    Day { day: u32, days_in_month: u32 },
+    /// outside `0..=23`
+    Hour(u32),
+    /// outside `0..=59`
+    Minute(u32),
+    /// outside `0..=59`
+    Second(u32),
+    /// at least one second, only allowed in a leap second (`second == 59`)
+    Nanoseconds(u32),
+    /// the result of a conversion or of arithmetic is out of range
+    Overflow,
+}
+
+impl fmt::Display for DateTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DateTimeError::Year(year) => {
+                write!(f, "year {} is outside {}..={}", year, MIN_YEAR, MAX_YEAR)
+            }
+            DateTimeError::Month(month) => write!(f, "month {} is outside 1..=12", month),
+            DateTimeError::Day { day, days_in_month } => {
+                write!(f, "day {} is outside 1..={}", day, days_in_month)
+            }
+            DateTimeError::Hour(hour) => write!(f, "hour {} is outside 0..=23", hour),
+            DateTimeError::Minute(minute) => write!(f, "minute {} is outside 0..=59", minute),
+            DateTimeError::Second(second) => write!(f, "second {} is outside 0..=59", second),
+            DateTimeError::Nanoseconds(ns) => {
+                write!(
+                    f,
+                    "{} nanoseconds is a second or more outside a leap second",
+                    ns
+                )
+            }
+            DateTimeError::Overflow => write!(f, "date and time out of range"),
+        }
+    }
+}
+
+/// Gregorian leap year, also for years before 1582 and below 0
+pub const fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Days in `month` of `year`, `None` for a month outside `1..=12`
+pub const fn days_in_month(year: i32, month: u32) -> Option<u32> {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => Some(31),
+        4 | 6 | 9 | 11 => Some(30),
+        2 if is_leap_year(year) => Some(29),
+        2 => Some(28),
+        _ => None,
+    }
+}
+
+impl UtcDateTime {
+    /// Check every field, the first one out of range is reported
+    ///
+    /// A leap second is written the way chrono does, `second == 59` with
+    /// `nanoseconds` of one second or more.
+    pub const fn validate(&self) -> Result<(), DateTimeError> {
+        if self.year < MIN_YEAR || self.year > MAX_YEAR {
+            return Err(DateTimeError::Year(self.year));
+        }
+        let days_in_month = match days_in_month(self.year, self.month) {
+            Some(days) => days,
+            None => return Err(DateTimeError::Month(self.month)),
+        };
+        if self.day < 1 || self.day > days_in_month {
+            return Err(DateTimeError::Day {
+                day: self.day,
+                days_in_month,
+            });
+        }
+        if self.hour > 23 {
+            return Err(DateTimeError::Hour(self.hour));
+        }
+        if self.minute > 59 {
+            return Err(DateTimeError::Minute(self.minute));
+        }
+        if self.second > 59 {
+            return Err(DateTimeError::Second(self.second));
+        }
+        let max_nanoseconds = if self.second == 59 {
+            2_000_000_000
+        } else {
+            1_000_000_000
+        };
+        if self.nanoseconds >= max_nanoseconds {
+            return Err(DateTimeError::Nanoseconds(self.nanoseconds));
+        }
+        Ok(())
+    }
+
+    pub const fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Milliseconds since 1970-01-01T00:00:00Z, rounded down
+    pub const fn to_epoch_millis(&self) -> Result<i64, DateTimeError> {
+        match self.epoch_parts() {
+            // cannot overflow within MIN_YEAR..=MAX_YEAR
+            Ok((secs, nanos)) => Ok(secs * 1000 + (nanos / 1_000_000) as i64),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Nanoseconds since 1970-01-01T00:00:00Z, `Overflow` outside the
+    /// years 1677 to 2262
+    pub const fn to_epoch_nanos(&self) -> Result<EpochNanos, DateTimeError> {
+        let (secs, nanos) = match self.epoch_parts() {
+            Ok(epoch) => epoch,
+            Err(e) => return Err(e),
+        };
+        match secs.checked_mul(1_000_000_000) {
+            Some(ns) => match ns.checked_add(nanos as i64) {
+                Some(ns) => Ok(ns),
+                None => Err(DateTimeError::Overflow),
+            },
+            None => Err(DateTimeError::Overflow),
+        }
+    }
+
+    /// `Overflow` outside `MIN_YEAR..=MAX_YEAR`
+    pub const fn from_epoch_millis(millis: i64) -> Result<Self, DateTimeError> {
+        let nanos = millis.rem_euclid(1000) as u32 * 1_000_000;
+        Self::from_epoch(millis.div_euclid(1000), nanos)
+    }
+
+    pub const fn from_epoch_nanos(nanos: EpochNanos) -> Self {
+        let secs = nanos.div_euclid(1_000_000_000);
+        let nanos = nanos.rem_euclid(1_000_000_000) as u32;
+        match Self::from_epoch(secs, nanos) {
+            Ok(dt) => dt,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// `self + duration`, `Overflow` past `MAX_YEAR`
+    pub const fn checked_add(&self, duration: Duration) -> Result<Self, DateTimeError> {
+        let (secs, nanos) = match self.epoch_parts() {
+            Ok(epoch) => epoch,
+            Err(e) => return Err(e),
+        };
+        if duration.as_secs() > i64::MAX as u64 {
+            return Err(DateTimeError::Overflow);
+        }
+        let nanos = nanos + duration.subsec_nanos();
+        match secs.checked_add(duration.as_secs() as i64 + (nanos / 1_000_000_000) as i64) {
+            Some(secs) => Self::from_epoch(secs, nanos % 1_000_000_000),
+            None => Err(DateTimeError::Overflow),
+        }
+    }
+
+    /// `self - duration`, `Overflow` before `MIN_YEAR`
+    pub const fn checked_sub(&self, duration: Duration) -> Result<Self, DateTimeError> {
+        let (secs, nanos) = match self.epoch_parts() {
+            Ok(epoch) => epoch,
+            Err(e) => return Err(e),
+        };
+        if duration.as_secs() > i64::MAX as u64 {
+            return Err(DateTimeError::Overflow);
+        }
+        let (borrow, nanos) = if nanos >= duration.subsec_nanos() {
+            (0, nanos - duration.subsec_nanos())
+        } else {
+            (1, nanos + 1_000_000_000 - duration.subsec_nanos())
+        };
+        match secs.checked_sub(duration.as_secs() as i64 + borrow) {
+            Some(secs) => Self::from_epoch(secs, nanos),
+            None => Err(DateTimeError::Overflow),
+        }
+    }
+
+    // whole seconds since the epoch and nanoseconds below one second
+    const fn epoch_parts(&self) -> Result<(i64, u32), DateTimeError> {
+        if let Err(e) = self.validate() {
+            return Err(e);
+        }
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let secs = days * SECS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        // a leap second counts as the first second of the next minute
+        let carry = (self.nanoseconds / 1_000_000_000) as i64;
+        Ok((secs + carry, self.nanoseconds % 1_000_000_000))
+    }
+
+    const fn from_epoch(secs: i64, nanoseconds: u32) -> Result<Self, DateTimeError> {
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        if year < MIN_YEAR as i64 || year > MAX_YEAR as i64 {
+            return Err(DateTimeError::Overflow);
+        }
+        let secs_of_day = secs.rem_euclid(SECS_PER_DAY) as u32;
+        Ok(UtcDateTime {
+            year: year as i32,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            minute: secs_of_day / 60 % 60,
+            second: secs_of_day % 60,
+            nanoseconds,
+        })
+    }
+}
+
+const SECS_PER_DAY: i64 = 86_400;
+
+// Days since 1970-01-01 of a proleptic Gregorian date, after Howard Hinnant's
+// `days_from_civil`. Years are counted from March so that the leap day ends
+// each 400 year era.
+pub(crate) const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// inverse of `days_from_civil`, (year, month, day)
+pub(crate) const fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Panics on overflow or an invalid `UtcDateTime`, see `checked_add`
+impl Add<Duration> for UtcDateTime {
+    type Output = UtcDateTime;
+
+    fn add(self, duration: Duration) -> UtcDateTime {
+        self.checked_add(duration)
+            .expect("overflow when adding a duration to a UtcDateTime")
+    }
+}
+
+/// Panics on overflow or an invalid `UtcDateTime`, see `checked_sub`
+impl Sub<Duration> for UtcDateTime {
+    type Output = UtcDateTime;
+
+    fn sub(self, duration: Duration) -> UtcDateTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting a duration from a UtcDateTime")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DateTimeError {}
+
+/// Why a string is not an RFC 3339 date and time
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// not of the form `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)`
+    Format,
+    /// well formed, but a field or the conversion to UTC is out of range
+    Invalid(DateTimeError),
+}
+
+#[cfg(feature = "std")]
+impl From<DateTimeError> for ParseError {
+    fn from(e: DateTimeError) -> Self {
+        ParseError::Invalid(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Format => write!(
+                f,
+                "expected an RFC 3339 date and time such as 2026-10-17T14:30:00Z"
+            ),
+            ParseError::Invalid(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// RFC 3339 in UTC, the fraction in groups of three digits and only if not
+/// zero. A leap second is written as second 60. Years outside `0..=9999`
+/// get a sign, as in ISO 8601.
+#[cfg(feature = "std")]
+impl fmt::Display for UtcDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if (0..=9999).contains(&self.year) {
+            write!(f, "{:04}", self.year)?;
+        } else {
+            write!(f, "{:+05}", self.year)?;
+        }
+        let (second, nanos) = if self.nanoseconds >= 1_000_000_000 {
+            (self.second + 1, self.nanoseconds - 1_000_000_000)
+        } else {
+            (self.second, self.nanoseconds)
+        };
+        write!(
+            f,
+            "-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.month, self.day, self.hour, self.minute, second
+        )?;
+        match nanos {
+            0 => {}
+            ns if ns % 1_000_000 == 0 => write!(f, ".{:03}", ns / 1_000_000)?,
+            ns if ns % 1_000 == 0 => write!(f, ".{:06}", ns / 1_000)?,
+            ns => write!(f, ".{:09}", ns)?,
+        }
+        f.write_str("Z")
+    }
+}
+
+/// RFC 3339 with `Z` or an offset such as `+03:00`, converted to UTC
+///
+/// `t` and a space are taken for `T`, `z` for `Z`. Fractions longer than
+/// nanoseconds are cut, a signed year of more than four digits is taken
+/// as `Display` writes it.
+#[cfg(feature = "std")]
+impl FromStr for UtcDateTime {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut p = Parser(s.as_bytes());
+        let year = p.year()?;
+        p.expect(b"-")?;
+        let month = p.digits(2)?;
+        p.expect(b"-")?;
+        let day = p.digits(2)?;
+        p.expect(b"Tt ")?;
+        let hour = p.digits(2)?;
+        p.expect(b":")?;
+        let minute = p.digits(2)?;
+        p.expect(b":")?;
+        let second = p.digits(2)?;
+        let nanoseconds = if p.expect(b".").is_ok() {
+            p.fraction()?
+        } else {
+            0
+        };
+        let offset_secs = match p.next() {
+            Some(b'Z' | b'z') => 0,
+            Some(sign @ (b'+' | b'-')) => {
+                let hours = p.digits(2)?;
+                p.expect(b":")?;
+                let minutes = p.digits(2)?;
+                if hours > 23 || minutes > 59 {
+                    return Err(ParseError::Format);
+                }
+                let secs = (hours * 3600 + minutes * 60) as i64;
+                if sign == b'-' {
+                    -secs
+                } else {
+                    secs
+                }
+            }
+            _ => return Err(ParseError::Format),
+        };
+        if !p.0.is_empty() {
+            return Err(ParseError::Format);
+        }
+
+        // the offset is whole minutes, a leap second stays one in UTC
+        let leap = second == 60;
+        let local = UtcDateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second: if leap { 59 } else { second },
+            nanoseconds,
+        };
+        local.validate()?;
+        let offset = Duration::from_secs(offset_secs.unsigned_abs());
+        let utc = if offset_secs >= 0 {
+            local.checked_sub(offset)?
+        } else {
+            local.checked_add(offset)?
+        };
+        Ok(UtcDateTime {
+            nanoseconds: utc.nanoseconds + if leap { 1_000_000_000 } else { 0 },
+            ..utc
+        })
+    }
+}
+
+// what is left of the string being parsed
+#[cfg(feature = "std")]
+struct Parser<'a>(&'a [u8]);
+
+#[cfg(feature = "std")]
+impl Parser<'_> {
+    fn next(&mut self) -> Option<u8> {
+        let (&first, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(first)
+    }
+
+    // one of `any`
+    fn expect(&mut self, any: &[u8]) -> Result<(), ParseError> {
+        match self.0.first() {
+            Some(c) if any.contains(c) => {
+                self.0 = &self.0[1..];
+                Ok(())
+            }
+            _ => Err(ParseError::Format),
+        }
+    }
+
+    fn digit_count(&self) -> usize {
+        self.0.iter().take_while(|c| c.is_ascii_digit()).count()
+    }
+
+    // exactly `n` digits, `n` at most 9
+    fn digits(&mut self, n: usize) -> Result<u32, ParseError> {
+        if self.digit_count() < n {
+            return Err(ParseError::Format);
+        }
+        let (digits, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(digits
+            .iter()
+            .fold(0, |value, c| value * 10 + (c - b'0') as u32))
+    }
+
+    // four digits, or a sign and four to nine digits
+    fn year(&mut self) -> Result<i32, ParseError> {
+        let sign = match self.0.first() {
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => return Ok(self.digits(4)? as i32),
+        };
+        self.0 = &self.0[1..];
+        let n = self.digit_count();
+        if !(4..=9).contains(&n) {
+            return Err(ParseError::Format);
+        }
+        Ok(sign * self.digits(n)? as i32)
+    }
+
+    // at least one digit, those past nanoseconds are dropped
+    fn fraction(&mut self) -> Result<u32, ParseError> {
+        let n = self.digit_count();
+        if n == 0 {
+            return Err(ParseError::Format);
+        }
+        let kept = n.min(9);
+        let nanos = self.digits(kept)? * 10u32.pow((9 - kept) as u32);
+        self.0 = &self.0[n - kept..];
+        Ok(nanos)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<DateTime<Utc>> for UtcDateTime {
+    fn from(dt: DateTime<Utc>) -> Self {
         Self {
             year: dt.year(),
             month: dt.month(),
@@ -26,16 +545,435 @@ impl From<chrono::DateTime<Utc>> for UtcDateTime {
     }
 }
 
-impl From<UtcDateTime> for chrono::DateTime<Utc> {
-    fn from(value: UtcDateTime) -> Self {
-        Utc.with_ymd_and_hms(
-            value.year,
-            value.month,
-            value.day,
-            value.hour,
-            value.minute,
-            value.second,
-        )
-        .unwrap()
+#[cfg(feature = "chrono")]
+impl TryFrom<UtcDateTime> for DateTime<Utc> {
+    type Error = DateTimeError;
+
+    fn try_from(value: UtcDateTime) -> Result<Self, Self::Error> {
+        value.validate()?;
+        let dt = NaiveDate::from_ymd_opt(value.year, value.month, value.day)
+            .and_then(|date| {
+                date.and_hms_nano_opt(value.hour, value.minute, value.second, value.nanoseconds)
+            })
+            .expect("validated fields are in chrono's range");
+        Ok(dt.and_utc())
+    }
+}
+
+#[cfg(test)]
+const fn date(year: i32, month: u32, day: u32) -> UtcDateTime {
+    UtcDateTime {
+        year,
+        month,
+        day,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        nanoseconds: 0,
+    }
+}
+
+#[test]
+fn leap_years() {
+    assert!(is_leap_year(2024) && is_leap_year(2000) && is_leap_year(0) && is_leap_year(-4));
+    assert!(!is_leap_year(2023) && !is_leap_year(1900) && !is_leap_year(2100) && !is_leap_year(-1));
+
+    assert_eq!(date(2024, 2, 29).validate(), Ok(()));
+    assert_eq!(date(2000, 2, 29).validate(), Ok(()));
+    let not_leap = Err(DateTimeError::Day {
+        day: 29,
+        days_in_month: 28,
+    });
+    assert_eq!(date(2023, 2, 29).validate(), not_leap);
+    assert_eq!(date(1900, 2, 29).validate(), not_leap);
+}
+
+#[test]
+fn out_of_range_fields_are_named() {
+    let dt = date(2023, 10, 17);
+    let cases = [
+        (UtcDateTime { month: 0, ..dt }, DateTimeError::Month(0)),
+        (UtcDateTime { month: 13, ..dt }, DateTimeError::Month(13)),
+        (
+            UtcDateTime { day: 0, ..dt },
+            DateTimeError::Day {
+                day: 0,
+                days_in_month: 31,
+            },
+        ),
+        (
+            UtcDateTime {
+                month: 4,
+                day: 31,
+                ..dt
+            },
+            DateTimeError::Day {
+                day: 31,
+                days_in_month: 30,
+            },
+        ),
+        (UtcDateTime { hour: 24, ..dt }, DateTimeError::Hour(24)),
+        (UtcDateTime { minute: 60, ..dt }, DateTimeError::Minute(60)),
+        (UtcDateTime { second: 60, ..dt }, DateTimeError::Second(60)),
+        (
+            UtcDateTime {
+                nanoseconds: 1_000_000_000,
+                ..dt
+            },
+            DateTimeError::Nanoseconds(1_000_000_000),
+        ),
+        (
+            UtcDateTime {
+                year: MAX_YEAR + 1,
+                ..dt
+            },
+            DateTimeError::Year(MAX_YEAR + 1),
+        ),
+        (
+            UtcDateTime {
+                year: i32::MIN,
+                ..dt
+            },
+            DateTimeError::Year(i32::MIN),
+        ),
+    ];
+    for (dt, err) in cases {
+        assert_eq!(dt.validate(), Err(err), "{:?}", dt);
+        assert_eq!(dt.to_epoch_millis(), Err(err));
+        #[cfg(feature = "chrono")]
+        assert_eq!(DateTime::<Utc>::try_from(dt), Err(err));
+    }
+}
+
+#[test]
+fn epoch_conversions() {
+    let dt = UtcDateTime {
+        hour: 14,
+        minute: 30,
+        second: 5,
+        nanoseconds: 123_456_789,
+        ..date(2023, 10, 17)
+    };
+    assert_eq!(dt.to_epoch_millis(), Ok(1_697_553_005_123));
+    assert_eq!(dt.to_epoch_nanos(), Ok(1_697_553_005_123_456_789));
+    assert_eq!(UtcDateTime::from_epoch_nanos(1_697_553_005_123_456_789), dt);
+    assert_eq!(
+        UtcDateTime::from_epoch_millis(1_697_553_005_123),
+        Ok(UtcDateTime {
+            nanoseconds: 123_000_000,
+            ..dt
+        })
+    );
+
+    assert_eq!(UtcDateTime::from_epoch_millis(0), Ok(date(1970, 1, 1)));
+    let before = UtcDateTime {
+        hour: 23,
+        minute: 59,
+        second: 59,
+        nanoseconds: 999_000_000,
+        ..date(1969, 12, 31)
+    };
+    assert_eq!(UtcDateTime::from_epoch_millis(-1), Ok(before));
+    assert_eq!(before.to_epoch_millis(), Ok(-1));
+
+    // a leap second is the start of the next day on the epoch scale
+    let leap = UtcDateTime {
+        nanoseconds: 1_500_000_000,
+        ..before
+    };
+    assert_eq!(leap.to_epoch_millis(), Ok(500));
+
+    // first and last days representable, epoch nanoseconds run out much earlier
+    let first = date(MIN_YEAR, 1, 1);
+    let last = UtcDateTime {
+        hour: 23,
+        minute: 59,
+        second: 59,
+        nanoseconds: 999_999_999,
+        ..date(MAX_YEAR, 12, 31)
+    };
+    for dt in [first, last] {
+        let millis = dt.to_epoch_millis().unwrap();
+        assert_eq!(
+            UtcDateTime::from_epoch_millis(millis),
+            Ok(UtcDateTime {
+                nanoseconds: dt.nanoseconds / 1_000_000 * 1_000_000,
+                ..dt
+            })
+        );
+        assert_eq!(dt.to_epoch_nanos(), Err(DateTimeError::Overflow));
+    }
+    let millis = last.to_epoch_millis().unwrap();
+    assert_eq!(
+        UtcDateTime::from_epoch_millis(millis + 1),
+        Err(DateTimeError::Overflow)
+    );
+    assert_eq!(
+        UtcDateTime::from_epoch_millis(i64::MIN),
+        Err(DateTimeError::Overflow)
+    );
+    assert_eq!(epoch_nanos_to_millis(-1), -1);
+    assert_eq!(
+        epoch_nanos_to_millis(1_697_553_005_123_456_789),
+        1_697_553_005_123
+    );
+    assert_eq!(UtcDateTime::from_epoch_nanos(i64::MIN).year, 1677);
+    assert_eq!(UtcDateTime::from_epoch_nanos(i64::MAX).year, 2262);
+}
+
+#[test]
+fn durations_cross_days_months_and_leap_days() {
+    let day = Duration::from_secs(86_400);
+    assert_eq!(date(2024, 2, 28) + day, date(2024, 2, 29));
+    assert_eq!(date(2023, 2, 28) + day, date(2023, 3, 1));
+    assert_eq!(date(2000, 2, 28) + day, date(2000, 2, 29));
+    assert_eq!(date(1900, 2, 28) + day, date(1900, 3, 1));
+    assert_eq!(date(2024, 1, 1) - day, date(2023, 12, 31));
+    assert_eq!(date(2024, 3, 1) - day, date(2024, 2, 29));
+    assert_eq!(date(2023, 10, 17) + 366 * day, date(2024, 10, 17));
+
+    let dt = UtcDateTime {
+        second: 59,
+        nanoseconds: 900_000_000,
+        ..date(2023, 12, 31)
+    };
+    let later = dt + Duration::from_millis(250);
+    assert_eq!(
+        later,
+        UtcDateTime {
+            second: 0,
+            minute: 0,
+            nanoseconds: 150_000_000,
+            ..date(2023, 12, 31)
+        } + Duration::from_secs(60)
+    );
+    assert_eq!(later - Duration::from_millis(250), dt);
+    assert!(later > dt && dt < date(2024, 1, 1));
+
+    assert_eq!(
+        date(MAX_YEAR, 12, 31).checked_add(day),
+        Err(DateTimeError::Overflow)
+    );
+    assert_eq!(
+        date(MIN_YEAR, 1, 1).checked_sub(Duration::from_nanos(1)),
+        Err(DateTimeError::Overflow)
+    );
+    assert_eq!(
+        date(2023, 1, 1).checked_add(Duration::MAX),
+        Err(DateTimeError::Overflow)
+    );
+    assert_eq!(
+        date(2023, 13, 1).checked_add(day),
+        Err(DateTimeError::Month(13))
+    );
+}
+
+#[test]
+fn every_day_of_a_leap_cycle_roundtrips() {
+    // 400 years plus a margin on both sides, one step per day
+    let start = date(1599, 12, 25).to_epoch_millis().unwrap() / 86_400_000;
+    let mut expected = date(1599, 12, 25);
+    for days in start..start + 146_097 + 20 {
+        let dt = UtcDateTime::from_epoch_millis(days * 86_400_000).unwrap();
+        assert_eq!(dt, expected);
+        assert_eq!(dt.to_epoch_millis(), Ok(days * 86_400_000));
+        let next = match days_in_month(dt.year, dt.month) {
+            Some(last) if dt.day < last => UtcDateTime {
+                day: dt.day + 1,
+                ..dt
+            },
+            _ if dt.month < 12 => UtcDateTime {
+                month: dt.month + 1,
+                day: 1,
+                ..dt
+            },
+            _ => date(dt.year + 1, 1, 1),
+        };
+        assert!(next > dt);
+        expected = next;
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn epoch_agrees_with_chrono() {
+    for millis in [
+        0,
+        -1,
+        1_697_553_005_123,
+        -62_135_596_800_000, // 0001-01-01
+        951_782_400_000,     // 2000-02-29
+        4_107_542_399_999,   // 2100-02-28T23:59:59.999
+    ] {
+        let dt = DateTime::<Utc>::from_timestamp_millis(millis).unwrap();
+        assert_eq!(UtcDateTime::from_epoch_millis(millis), Ok(dt.into()));
+        assert_eq!(UtcDateTime::from(dt).to_epoch_millis(), Ok(millis));
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_roundtrip_keeps_nanoseconds() {
+    let values = [
+        UtcDateTime {
+            year: 2023,
+            month: 10,
+            day: 17,
+            hour: 14,
+            minute: 30,
+            second: 5,
+            nanoseconds: 123_456_789,
+        },
+        // last and first instants of a year, of chrono's range, and a leap second
+        UtcDateTime {
+            hour: 23,
+            minute: 59,
+            second: 59,
+            nanoseconds: 999_999_999,
+            ..date(2023, 12, 31)
+        },
+        date(2024, 1, 1),
+        date(MIN_YEAR, 1, 1),
+        UtcDateTime {
+            hour: 23,
+            minute: 59,
+            second: 59,
+            nanoseconds: 999_999_999,
+            ..date(MAX_YEAR, 12, 31)
+        },
+        UtcDateTime {
+            hour: 23,
+            minute: 59,
+            second: 59,
+            nanoseconds: 1_500_000_000,
+            ..date(2016, 12, 31)
+        },
+    ];
+    for udt in values {
+        let dt = DateTime::<Utc>::try_from(udt).unwrap();
+        assert_eq!(UtcDateTime::from(dt), udt);
+    }
+
+    let dt = DateTime::<Utc>::try_from(values[0]).unwrap();
+    assert_eq!(dt.timestamp_millis(), 1_697_553_005_123);
+    assert_eq!(dt.timestamp_subsec_nanos(), 123_456_789);
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn year_range_is_chronos() {
+    assert_eq!(NaiveDate::MIN.year(), MIN_YEAR);
+    assert_eq!(NaiveDate::MAX.year(), MAX_YEAR);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn rfc3339_roundtrip() {
+    let dt = UtcDateTime {
+        hour: 14,
+        minute: 30,
+        ..date(2026, 10, 17)
+    };
+    assert_eq!(dt.to_string(), "2026-10-17T14:30:00Z");
+    assert_eq!("2026-10-17T14:30:00Z".parse(), Ok(dt));
+
+    for (nanoseconds, text) in [
+        (500_000_000, "2026-10-17T14:30:00.500Z"),
+        (123_456_000, "2026-10-17T14:30:00.123456Z"),
+        (1, "2026-10-17T14:30:00.000000001Z"),
+    ] {
+        let dt = UtcDateTime { nanoseconds, ..dt };
+        assert_eq!(dt.to_string(), text);
+        assert_eq!(text.parse(), Ok(dt));
+    }
+
+    let leap = UtcDateTime {
+        hour: 23,
+        minute: 59,
+        second: 59,
+        nanoseconds: 1_250_000_000,
+        ..date(2016, 12, 31)
+    };
+    assert_eq!(leap.to_string(), "2016-12-31T23:59:60.250Z");
+    assert_eq!("2016-12-31T23:59:60.250Z".parse(), Ok(leap));
+
+    for year in [0, -1, 10_000, MIN_YEAR, MAX_YEAR] {
+        let dt = date(year, 3, 1);
+        assert_eq!(dt.to_string().parse(), Ok(dt), "{}", dt);
+    }
+    assert_eq!(date(-1, 3, 1).to_string(), "-0001-03-01T00:00:00Z");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn rfc3339_offsets_convert_to_utc() {
+    let utc = UtcDateTime {
+        hour: 14,
+        minute: 30,
+        ..date(2026, 10, 17)
+    };
+    assert_eq!("2026-10-17T17:30:00+03:00".parse(), Ok(utc));
+    assert_eq!("2026-10-17T09:00:00-05:30".parse(), Ok(utc));
+    assert_eq!("2026-10-17t14:30:00z".parse(), Ok(utc));
+    assert_eq!("2026-10-17 14:30:00-00:00".parse(), Ok(utc));
+
+    // across midnight, the end of a month and a leap day
+    let new_year = date(2027, 1, 1);
+    assert_eq!("2026-12-31T21:00:00-03:00".parse(), Ok(new_year));
+    assert_eq!(
+        "2024-03-01T01:00:00+02:00".parse(),
+        Ok(UtcDateTime {
+            hour: 23,
+            ..date(2024, 2, 29)
+        })
+    );
+
+    // more digits than nanoseconds are cut
+    assert_eq!(
+        "2026-10-17T14:30:00.1234567891Z".parse(),
+        Ok(UtcDateTime {
+            nanoseconds: 123_456_789,
+            ..utc
+        })
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn rfc3339_rejects() {
+    for text in [
+        "",
+        "2026-10-17",
+        "2026-10-17T14:30Z",
+        "2026-10-17T14:30:00",
+        "2026-10-17T14:30:00+0300",
+        "2026-10-17T14:30:00+24:00",
+        "2026-10-17T14:30:00.Z",
+        "2026-10-17T14:30:00Z ",
+        "26-10-17T14:30:00Z",
+        "+026-10-17T14:30:00Z",
+        "2026/10/17T14:30:00Z",
+    ] {
+        assert_eq!(
+            text.parse::<UtcDateTime>(),
+            Err(ParseError::Format),
+            "{}",
+            text
+        );
     }
+    assert_eq!(
+        "2026-02-29T00:00:00Z".parse::<UtcDateTime>(),
+        Err(ParseError::Invalid(DateTimeError::Day {
+            day: 29,
+            days_in_month: 28
+        }))
+    );
+    assert_eq!(
+        "2026-10-17T14:60:00Z".parse::<UtcDateTime>(),
+        Err(ParseError::Invalid(DateTimeError::Minute(60)))
+    );
+    assert_eq!(
+        "+262142-12-31T23:00:00-01:00".parse::<UtcDateTime>(),
+        Err(ParseError::Invalid(DateTimeError::Overflow))
+    );
 }
@@ -0,0 +1,90 @@
+//! Duplicate suppression for retransmitted commands
+//!
+//! The host resends a command under the same `Seq` until the response gets
+//! through, so a lost response means the device sees the command again.
+//! `ResponseCache` remembers the response to the most recent command and
+//! hands it back for a repeated `Seq`, the command itself is applied once.
+//!
+//! The `Seq` alone is not enough: a restarted host, or a second one on the
+//! bus, may open with the `Seq` that is cached. Its command would be answered
+//! with the old response and never carried out. So the cache also keeps a
+//! `Digest` of the command and replays only if both match.
+//!
+//! One entry is enough as the host has at most one request in flight.
+
+use crate::{encoded_size::MaxEncodedSize, wire, Command, Response, Seq, CKSUM};
+
+/// CRC of the serialized command
+pub type Digest = u32;
+
+/// Digest of `cmd` as it was carried in the frame
+///
+/// Not the CRC of the frame: with `auth` that also covers the counter, which
+/// is new on every resend.
+pub fn digest(cmd: &Command) -> Digest {
+    let mut buf = [0; Command::MAX_ENCODED_SIZE];
+    // sized for the widest command, cannot fail
+    let n = wire::serialize(&mut buf, cmd).unwrap_or(0);
+    CKSUM.checksum(&buf[..n])
+}
+
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    last: Option<(Seq, Digest, Response)>,
+}
+
+impl ResponseCache {
+    pub const fn new() -> Self {
+        ResponseCache { last: None }
+    }
+
+    /// Response already sent for `seq`, `None` unless `seq` is a retransmission
+    /// of the command with `digest`
+    pub fn replay(&self, seq: Seq, digest: Digest) -> Option<&Response> {
+        match &self.last {
+            Some((last_seq, last_digest, rsp)) if *last_seq == seq && *last_digest == digest => {
+                Some(rsp)
+            }
+            _ => None,
+        }
+    }
+
+    /// Remember `rsp` as the answer to `seq`, replacing the previous entry
+    pub fn store(&mut self, seq: Seq, digest: Digest, rsp: Response) {
+        self.last = Some((seq, digest, rsp));
+    }
+}
+
+#[cfg(test)]
+use crate::Action;
+
+#[test]
+fn replays_only_the_last_seq() {
+    let blink_off = digest(&Command::Action(Action::BlinkOff, 1));
+    let mut cache = ResponseCache::new();
+    assert_eq!(cache.replay(1, blink_off), None);
+
+    cache.store(1, blink_off, Response::SetOk);
+    assert_eq!(cache.replay(1, blink_off), Some(&Response::SetOk));
+    assert_eq!(cache.replay(2, blink_off), None);
+
+    cache.store(2, blink_off, Response::NotOK);
+    assert_eq!(cache.replay(1, blink_off), None);
+    assert_eq!(cache.replay(2, blink_off), Some(&Response::NotOK));
+}
+
+#[test]
+fn same_seq_with_another_command_is_not_replayed() {
+    let blink_off = digest(&Command::Action(Action::BlinkOff, 1));
+    let rgb_on = digest(&Command::Action(Action::SetRgb(true), 1));
+    assert_ne!(blink_off, rgb_on);
+
+    let mut cache = ResponseCache::new();
+    cache.store(7, blink_off, Response::SetOk);
+    // a new host that happened to start at seq 7
+    assert_eq!(cache.replay(7, rgb_on), None);
+
+    cache.store(7, rgb_on, Response::NotOK);
+    assert_eq!(cache.replay(7, rgb_on), Some(&Response::NotOK));
+    assert_eq!(cache.replay(7, blink_off), None);
+}
@@ -0,0 +1,278 @@
+//! Upper bounds on the serialized size of wire types
+//!
+//! `size_of` measures the in-memory layout, padding included, which differs
+//! between the riscv32 firmware and an x86_64 host. `MaxEncodedSize` adds up
+//! the widest encoding of each field under the active `wire` backend instead,
+//! so a bound is the same on every target. Enums take their largest variant
+//! plus the tag, one byte with either backend.
+//!
+//! The frame sizes are pinned below, `cargo check --target
+//! riscv32imc-unknown-none-elf` (or any other target) evaluates the pins for
+//! that target.
+
+use core::marker::PhantomData;
+
+use crate::{
+    batch::Batch,
+    date_time::UtcDateTime,
+    encoder::encode_in_place,
+    error::ErrorCode,
+    event::Event,
+    hello::{HelloInfo, FW_VERSION_LEN, GIT_HASH_LEN},
+    time_zone::{DstRule, TimeZone, Transition},
+    Action, Command, Faults, Message, Response, Seq, Upstream, CRC_LEN,
+};
+
+pub trait MaxEncodedSize {
+    /// Most bytes `wire::serialize` writes for any value of the type
+    const MAX_ENCODED_SIZE: usize;
+}
+
+/// Serialized `seq | T`, what a frame carries before the CRC and COBS
+pub const fn max_payload_len<T: MaxEncodedSize>() -> usize {
+    Seq::MAX_ENCODED_SIZE + T::MAX_ENCODED_SIZE
+}
+
+/// Encoded frame carrying a `T`, delimiter included
+///
+/// With the `auth` feature there is room for the counter and MAC of an
+/// authenticated frame.
+pub const fn max_frame_len<T: MaxEncodedSize>() -> usize {
+    corncobs::max_encoded_len(max_payload_len::<T>() + AUTH_LEN + CRC_LEN)
+}
+
+#[cfg(feature = "auth")]
+const AUTH_LEN: usize = crate::auth::AUTH_LEN;
+#[cfg(not(feature = "auth"))]
+const AUTH_LEN: usize = 0;
+
+// fixed width with ssmarshal, a LEB128 varint (zigzag for signed) with postcard
+const fn int(bytes: usize) -> usize {
+    if cfg!(feature = "postcard") {
+        (bytes * 8).div_ceil(7)
+    } else {
+        bytes
+    }
+}
+
+// the tag plus the largest variant, unit variants count as 0
+const fn enumeration(variants: &[usize]) -> usize {
+    let mut max = 0;
+    let mut i = 0;
+    while i < variants.len() {
+        if variants[i] > max {
+            max = variants[i];
+        }
+        i += 1;
+    }
+    // both backends write fewer than 128 variants as a single byte
+    1 + max
+}
+
+macro_rules! int_size {
+    ($($t:ty => $bytes:expr),*) => {
+        $(impl MaxEncodedSize for $t {
+            const MAX_ENCODED_SIZE: usize = int($bytes);
+        })*
+    };
+}
+
+int_size!(u16 => 2, u32 => 4, u64 => 8, i16 => 2, i32 => 4, i64 => 8);
+
+// single bytes are written as they are by both backends
+impl MaxEncodedSize for u8 {
+    const MAX_ENCODED_SIZE: usize = 1;
+}
+
+impl MaxEncodedSize for bool {
+    const MAX_ENCODED_SIZE: usize = 1;
+}
+
+// a one byte tag with both backends
+impl<T: MaxEncodedSize> MaxEncodedSize for Option<T> {
+    const MAX_ENCODED_SIZE: usize = 1 + T::MAX_ENCODED_SIZE;
+}
+
+// arrays are tuples to both backends, no length prefix
+impl<T: MaxEncodedSize, const N: usize> MaxEncodedSize for [T; N] {
+    const MAX_ENCODED_SIZE: usize = N * T::MAX_ENCODED_SIZE;
+}
+
+impl<A: MaxEncodedSize, B: MaxEncodedSize> MaxEncodedSize for (A, B) {
+    const MAX_ENCODED_SIZE: usize = A::MAX_ENCODED_SIZE + B::MAX_ENCODED_SIZE;
+}
+
+impl<A: MaxEncodedSize, B: MaxEncodedSize, C: MaxEncodedSize> MaxEncodedSize for (A, B, C) {
+    const MAX_ENCODED_SIZE: usize = A::MAX_ENCODED_SIZE + B::MAX_ENCODED_SIZE + C::MAX_ENCODED_SIZE;
+}
+
+impl<A: MaxEncodedSize, B: MaxEncodedSize, C: MaxEncodedSize, D: MaxEncodedSize> MaxEncodedSize
+    for (A, B, C, D)
+{
+    const MAX_ENCODED_SIZE: usize =
+        A::MAX_ENCODED_SIZE + B::MAX_ENCODED_SIZE + C::MAX_ENCODED_SIZE + D::MAX_ENCODED_SIZE;
+}
+
+impl MaxEncodedSize for UtcDateTime {
+    const MAX_ENCODED_SIZE: usize = i32::MAX_ENCODED_SIZE + 6 * u32::MAX_ENCODED_SIZE;
+}
+
+impl MaxEncodedSize for Transition {
+    const MAX_ENCODED_SIZE: usize = <[u8; 3]>::MAX_ENCODED_SIZE + u16::MAX_ENCODED_SIZE;
+}
+
+impl MaxEncodedSize for DstRule {
+    const MAX_ENCODED_SIZE: usize = i16::MAX_ENCODED_SIZE + 2 * Transition::MAX_ENCODED_SIZE;
+}
+
+impl MaxEncodedSize for TimeZone {
+    const MAX_ENCODED_SIZE: usize = i16::MAX_ENCODED_SIZE + Option::<DstRule>::MAX_ENCODED_SIZE;
+}
+
+impl MaxEncodedSize for HelloInfo {
+    const MAX_ENCODED_SIZE: usize = u16::MAX_ENCODED_SIZE
+        + <[u8; FW_VERSION_LEN]>::MAX_ENCODED_SIZE
+        + <[u8; GIT_HASH_LEN]>::MAX_ENCODED_SIZE
+        + 2 * u32::MAX_ENCODED_SIZE;
+}
+
+impl MaxEncodedSize for Message {
+    const MAX_ENCODED_SIZE: usize = enumeration(&[
+        UtcDateTime::MAX_ENCODED_SIZE,
+        u32::MAX_ENCODED_SIZE,
+        <(u32, u32)>::MAX_ENCODED_SIZE,
+        <(UtcDateTime, u32, u32)>::MAX_ENCODED_SIZE,
+        i64::MAX_ENCODED_SIZE,
+        <(i64, u32, u32)>::MAX_ENCODED_SIZE,
+        TimeZone::MAX_ENCODED_SIZE,
+    ]);
+}
+
+impl MaxEncodedSize for Action {
+    const MAX_ENCODED_SIZE: usize = enumeration(&[
+        UtcDateTime::MAX_ENCODED_SIZE,
+        0,
+        <(u32, u32)>::MAX_ENCODED_SIZE,
+        <(UtcDateTime, u32, u32)>::MAX_ENCODED_SIZE,
+        bool::MAX_ENCODED_SIZE,
+        i64::MAX_ENCODED_SIZE,
+        <(i64, u32, u32)>::MAX_ENCODED_SIZE,
+        TimeZone::MAX_ENCODED_SIZE,
+    ]);
+}
+
+impl MaxEncodedSize for Command {
+    const MAX_ENCODED_SIZE: usize = enumeration(&[
+        <(u32, Message, u32)>::MAX_ENCODED_SIZE,
+        <(u32, u32, u32)>::MAX_ENCODED_SIZE,
+        u32::MAX_ENCODED_SIZE,
+        <(Action, u32)>::MAX_ENCODED_SIZE,
+        <(Batch, u32)>::MAX_ENCODED_SIZE,
+    ]);
+}
+
+impl MaxEncodedSize for ErrorCode {
+    const MAX_ENCODED_SIZE: usize = enumeration(&[]);
+}
+
+impl MaxEncodedSize for Response {
+    const MAX_ENCODED_SIZE: usize = enumeration(&[
+        <(u32, u32, i64, u32)>::MAX_ENCODED_SIZE,
+        0,
+        0,
+        0,
+        0,
+        HelloInfo::MAX_ENCODED_SIZE,
+        <(ErrorCode, u32)>::MAX_ENCODED_SIZE,
+        <(u8, ErrorCode, u32)>::MAX_ENCODED_SIZE,
+    ]);
+}
+
+impl MaxEncodedSize for Event {
+    const MAX_ENCODED_SIZE: usize = enumeration(&[
+        i64::MAX_ENCODED_SIZE,
+        i64::MAX_ENCODED_SIZE,
+        0,
+        0,
+        u32::MAX_ENCODED_SIZE,
+    ]);
+}
+
+impl MaxEncodedSize for Upstream {
+    const MAX_ENCODED_SIZE: usize =
+        enumeration(&[Response::MAX_ENCODED_SIZE, <(Event, u32)>::MAX_ENCODED_SIZE]);
+}
+
+/// Types sent as the payload of a frame
+pub trait Framed: MaxEncodedSize + serde::Serialize {
+    /// `[u8; max_frame_len::<Self>()]`, stable Rust cannot size an array by a
+    /// generic parameter so each type names its own
+    type Buf: AsRef<[u8]> + AsMut<[u8]>;
+    const EMPTY: Self::Buf;
+}
+
+macro_rules! framed {
+    ($($t:ty),*) => {
+        $(impl Framed for $t {
+            type Buf = [u8; max_frame_len::<$t>()];
+            const EMPTY: Self::Buf = [0; max_frame_len::<$t>()];
+        })*
+    };
+}
+
+framed!(Command, Upstream);
+
+/// Encode buffer that holds any frame carrying a `T`
+pub struct FrameBuf<T: Framed> {
+    buf: T::Buf,
+    _t: PhantomData<T>,
+}
+
+impl<T: Framed> Default for FrameBuf<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Framed> FrameBuf<T> {
+    pub const fn new() -> Self {
+        FrameBuf {
+            buf: T::EMPTY,
+            _t: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.as_ref().len()
+    }
+
+    /// Serialize `seq` and `t` into a CRC/COBS frame, see `serialize_crc_cobs`
+    pub fn encode(&mut self, seq: Seq, t: &T) -> Result<&[u8], Faults> {
+        let n = encode_in_place(seq, t, self.buf.as_mut())?;
+        Ok(&self.buf.as_ref()[..n])
+    }
+
+    /// Authenticated frame under the next counter of `signer`, see `auth`
+    #[cfg(feature = "auth")]
+    pub fn encode_signed(
+        &mut self,
+        seq: Seq,
+        t: &T,
+        signer: &mut crate::auth::Signer,
+    ) -> Result<&[u8], Faults> {
+        let n = signer.encode_in_place(seq, t, self.buf.as_mut())?;
+        Ok(&self.buf.as_ref()[..n])
+    }
+}
+
+// Frame sizes per backend, the same whatever the target
+#[cfg(not(feature = "postcard"))]
+const _: () = {
+    assert!(max_frame_len::<Command>() == 165 + AUTH_LEN);
+    assert!(max_frame_len::<Upstream>() == 44 + AUTH_LEN);
+};
+#[cfg(feature = "postcard")]
+const _: () = {
+    assert!(max_frame_len::<Command>() == 203 + AUTH_LEN);
+    assert!(max_frame_len::<Upstream>() == 48 + AUTH_LEN);
+};
@@ -0,0 +1,361 @@
+//! Copy free CRC/COBS frame encoding
+//!
+//! `FrameWriter` COBS-encodes bytes as they are written, updating the CRC on
+//! the way, and hands the encoded bytes to a `ByteSink` such as a UART. At
+//! most 255 bytes are buffered.
+//!
+//! `encode_in_place` does the same within a single buffer: the payload is
+//! serialized towards the end of the buffer and encoded forward over itself,
+//! the COBS overhead guarantees the write position never overtakes the read
+//! position.
+
+use corncobs::ZERO;
+use crc::{Crc, Digest};
+
+use crate::{wire, Faults, Seq, CKSUM, CRC_LEN};
+
+// longest COBS block, code byte plus 254 non-zero bytes
+const BLOCK: usize = 255;
+
+static CRC: &Crc<u32> = &CKSUM;
+
+/// Destination of encoded bytes
+pub trait ByteSink {
+    type Error;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Any `FnMut(&[u8]) -> Result<(), E>` is a sink, e.g. `|b| tx.write_bytes(b)`
+impl<E, F: FnMut(&[u8]) -> Result<(), E>> ByteSink for F {
+    type Error = E;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        self(bytes)
+    }
+}
+
+/// Failure while streaming a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError<E> {
+    /// the value could not be serialized
+    Fault(Faults),
+    /// the sink rejected a write
+    Sink(E),
+}
+
+impl<E> From<Faults> for EncodeError<E> {
+    fn from(fault: Faults) -> Self {
+        EncodeError::Fault(fault)
+    }
+}
+
+/// Streaming CRC/COBS encoder
+///
+/// Output is byte for byte identical to `corncobs::encode_buf` of
+/// `bytes | crc(bytes)`, including the trailing delimiter. Completed blocks
+/// are held back until the buffer fills up, so a typical frame reaches the
+/// sink in a single write.
+pub struct FrameWriter<S: ByteSink> {
+    sink: S,
+    digest: Digest<'static, u32>,
+    buf: [u8; BLOCK],
+    // index of the current block's code byte, filled in when the block closes
+    code: usize,
+    w: usize,
+    // the previous block was a full one, so no zero is implied after it
+    after_full: bool,
+    written: usize,
+}
+
+impl<S: ByteSink> FrameWriter<S> {
+    pub fn new(sink: S) -> Self {
+        FrameWriter {
+            sink,
+            digest: CRC.digest(),
+            buf: [0; BLOCK],
+            code: 0,
+            w: 1,
+            after_full: false,
+            written: 0,
+        }
+    }
+
+    /// Encode `bytes` as part of the frame payload
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+        self.digest.update(bytes);
+        self.encode(bytes)
+    }
+
+    /// Append the CRC and the delimiter, returns the number of bytes written
+    ///
+    /// The writer is ready for the next frame afterwards.
+    pub fn finish(&mut self) -> Result<usize, S::Error> {
+        let crc = core::mem::replace(&mut self.digest, CRC.digest()).finalize();
+        self.encode(&crc.to_le_bytes())?;
+
+        if self.after_full && self.w == self.code + 1 {
+            // corncobs does not emit an empty block after a full one
+            self.w = self.code;
+        } else {
+            self.buf[self.code] = (self.w - self.code) as u8;
+        }
+        if self.w < BLOCK {
+            self.buf[self.w] = ZERO;
+            self.sink.write(&self.buf[..=self.w])?;
+        } else {
+            self.sink.write(&self.buf)?;
+            self.sink.write(&[ZERO])?;
+        }
+
+        let n = self.written + self.w + 1;
+        self.code = 0;
+        self.w = 1;
+        self.after_full = false;
+        self.written = 0;
+        Ok(n)
+    }
+
+    fn encode(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+        for &b in bytes {
+            if self.w == BLOCK {
+                self.drain()?;
+            }
+            self.after_full = false;
+            if b == ZERO {
+                self.close()?;
+            } else {
+                self.buf[self.w] = b;
+                self.w += 1;
+                if self.w - self.code == BLOCK {
+                    self.close()?;
+                    self.after_full = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // fill in the code byte and open the next block
+    fn close(&mut self) -> Result<(), S::Error> {
+        self.buf[self.code] = (self.w - self.code) as u8;
+        self.code = self.w;
+        if self.w == BLOCK {
+            self.drain()?;
+        }
+        self.w += 1;
+        Ok(())
+    }
+
+    // hand the completed blocks to the sink, keep the open one
+    fn drain(&mut self) -> Result<(), S::Error> {
+        self.sink.write(&self.buf[..self.code])?;
+        self.written += self.code;
+        self.buf.copy_within(self.code..self.w, 0);
+        self.w -= self.code;
+        self.code = 0;
+        Ok(())
+    }
+}
+
+/// Serialize seq and T and stream the encoded frame into `sink`
+///
+/// `scratch` only needs to hold the serialized `(seq, t)`, not the encoded
+/// frame. Returns the number of bytes written to `sink`.
+pub fn serialize_crc_cobs_to<T: serde::Serialize, S: ByteSink>(
+    seq: Seq,
+    t: &T,
+    scratch: &mut [u8],
+    sink: S,
+) -> Result<usize, EncodeError<S::Error>> {
+    let n_ser = wire::serialize(scratch, &(seq, t))?;
+
+    let mut writer = FrameWriter::new(sink);
+    writer.write(&scratch[..n_ser]).map_err(EncodeError::Sink)?;
+    writer.finish().map_err(EncodeError::Sink)
+}
+
+/// Room left in front of the payload for COBS code bytes, enough for any
+/// payload that fits in an `n` byte buffer
+const fn in_place_offset(n: usize) -> usize {
+    1 + n / 254
+}
+
+/// Serialize seq and T into `buf` and COBS-encode it in place, CRC appended
+///
+/// Returns the length of the encoded frame at the start of `buf`.
+pub(crate) fn encode_in_place<T: serde::Serialize>(
+    seq: Seq,
+    t: &T,
+    buf: &mut [u8],
+) -> Result<usize, Faults> {
+    encode_in_place_sealed(seq, t, buf, 0, |_, _| {})
+}
+
+/// `encode_in_place` with `trailer_len` more bytes between payload and CRC
+///
+/// `seal` is handed the serialized `seq | payload` and fills in the trailer,
+/// which the CRC then covers along with the payload.
+pub(crate) fn encode_in_place_sealed<T: serde::Serialize>(
+    seq: Seq,
+    t: &T,
+    buf: &mut [u8],
+    trailer_len: usize,
+    seal: impl FnOnce(&[u8], &mut [u8]),
+) -> Result<usize, Faults> {
+    let off = in_place_offset(buf.len());
+    if off > buf.len() {
+        return Err(Faults::BufferTooSmall);
+    }
+    let n_ser = wire::serialize(&mut buf[off..], &(seq, t))?;
+    let n = n_ser + trailer_len;
+    if off + n > buf.len() || corncobs::max_encoded_len(n + CRC_LEN) > buf.len() {
+        return Err(Faults::BufferTooSmall);
+    }
+    let (payload, trailer) = buf[off..off + n].split_at_mut(n_ser);
+    seal(payload, trailer);
+    let crc = CRC.checksum(&buf[off..off + n]);
+
+    let mut enc = InPlace {
+        buf,
+        code: 0,
+        w: 1,
+        after_full: false,
+    };
+    for r in off..off + n {
+        // read before write, w <= r holds by choice of `off`
+        let b = enc.buf[r];
+        enc.push(b);
+    }
+    for b in crc.to_le_bytes() {
+        enc.push(b);
+    }
+    Ok(enc.finish())
+}
+
+struct InPlace<'a> {
+    buf: &'a mut [u8],
+    // index of the current block's code byte
+    code: usize,
+    w: usize,
+    after_full: bool,
+}
+
+impl InPlace<'_> {
+    fn push(&mut self, b: u8) {
+        self.after_full = false;
+        if b == ZERO {
+            self.close(self.w - self.code);
+        } else {
+            self.buf[self.w] = b;
+            self.w += 1;
+            if self.w - self.code == BLOCK {
+                self.close(BLOCK);
+                self.after_full = true;
+            }
+        }
+    }
+
+    fn close(&mut self, code: usize) {
+        self.buf[self.code] = code as u8;
+        self.code = self.w;
+        self.w += 1;
+    }
+
+    fn finish(mut self) -> usize {
+        if self.after_full && self.w == self.code + 1 {
+            // no empty block after a full one, reuse its slot for the delimiter
+            self.w = self.code;
+        } else {
+            self.buf[self.code] = (self.w - self.code) as u8;
+        }
+        self.buf[self.w] = ZERO;
+        self.w + 1
+    }
+}
+
+#[cfg(test)]
+fn reference_frame(payload: &[u8]) -> Vec<u8> {
+    let mut raw = payload.to_vec();
+    raw.extend_from_slice(&CKSUM.checksum(payload).to_le_bytes());
+    let mut out = vec![0; corncobs::max_encoded_len(raw.len())];
+    let n = corncobs::encode_buf(&raw, &mut out);
+    out.truncate(n);
+    out
+}
+
+#[test]
+fn writer_matches_corncobs_across_block_boundaries() {
+    // runs of non-zero bytes around the 254 byte block limit, with and without zeros
+    for len in [0, 1, 249, 250, 253, 254, 255, 507, 508, 600] {
+        for zero_every in [0, 1, 7, 254] {
+            let payload: Vec<u8> = (0..len)
+                .map(|i| {
+                    if zero_every != 0 && i % zero_every == 0 {
+                        0
+                    } else {
+                        1 + (i % 200) as u8
+                    }
+                })
+                .collect();
+
+            let mut out = Vec::new();
+            let mut writer = FrameWriter::new(|b: &[u8]| {
+                out.extend_from_slice(b);
+                Ok::<(), ()>(())
+            });
+            // split writes must not change the output
+            let (a, b) = payload.split_at(len / 3);
+            writer.write(a).unwrap();
+            writer.write(b).unwrap();
+            let n = writer.finish().unwrap();
+
+            assert_eq!(
+                out,
+                reference_frame(&payload),
+                "len {len}, zero every {zero_every}"
+            );
+            assert_eq!(n, out.len());
+        }
+    }
+}
+
+#[test]
+fn in_place_matches_streaming() {
+    let value = [[0x55u8; 30]; 10];
+    let mut buf = [0u8; 320];
+    let n = encode_in_place(7, &value, &mut buf).unwrap();
+
+    let mut scratch = [0u8; 310];
+    let mut out = Vec::new();
+    serialize_crc_cobs_to(7, &value, &mut scratch, |b: &[u8]| {
+        out.extend_from_slice(b);
+        Ok::<(), ()>(())
+    })
+    .unwrap();
+    assert_eq!(&buf[..n], &out[..]);
+}
+
+#[test]
+fn sink_error_is_reported() {
+    let mut scratch = [0u8; 16];
+    let res = serialize_crc_cobs_to(1, &5u32, &mut scratch, |_: &[u8]| Err("uart"));
+    assert_eq!(res, Err(EncodeError::Sink("uart")));
+}
+
+#[test]
+fn writer_is_reusable_after_finish() {
+    let frames = core::cell::RefCell::new(Vec::new());
+    let mut writer = FrameWriter::new(|b: &[u8]| {
+        frames.borrow_mut().extend_from_slice(b);
+        Ok::<(), ()>(())
+    });
+    writer.write(&[1; 300]).unwrap();
+    writer.finish().unwrap();
+    writer.write(&[0, 2]).unwrap();
+    writer.finish().unwrap();
+
+    let mut expected = reference_frame(&[1; 300]);
+    expected.extend(reference_frame(&[0, 2]));
+    assert_eq!(frames.into_inner(), expected);
+}
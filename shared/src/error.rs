@@ -0,0 +1,163 @@
+//! Error codes carried by `Response::Error`
+//!
+//! Each code comes with a `Detail` word, the offending value where there is
+//! one (a frequency, an id, a byte count) and `0` otherwise.
+
+use serde_derive::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::time_zone::TimeZone;
+use crate::{date_time::UtcDateTime, Action};
+
+pub type Detail = u32;
+
+/// Highest blink frequency the device accepts
+pub const MAX_FREQ_HZ: u32 = 100;
+/// Longest blink the device accepts, one day
+pub const MAX_DURATION_SECS: u32 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(C)]
+pub enum ErrorCode {
+    /// `BlinkAt` before the device time was set
+    TimeNotSet,
+    /// the date and time fields do not form a valid date
+    InvalidDate,
+    /// frequency outside `1..=MAX_FREQ_HZ`, detail is the frequency
+    FrequencyOutOfRange,
+    /// duration outside `1..=MAX_DURATION_SECS`, detail is the duration
+    DurationOutOfRange,
+    /// id or id/message pairing the device does not know, detail is the id
+    UnknownCommand,
+    /// the request frame failed its CRC check
+    CrcMismatch,
+    /// the request frame did not fit the receive buffer, detail is its length
+    BufferOverflow,
+    /// the request frame failed authentication, resending it will not help
+    AuthFailed,
+    /// offset or DST rule of a `SetTimeZone` out of range
+    InvalidTimeZone,
+}
+
+impl ErrorCode {
+    /// Human readable explanation, for the host CLI and logs
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::TimeNotSet => "the device time has not been set, send SetTime first",
+            ErrorCode::InvalidDate => "the date and time do not form a valid date",
+            ErrorCode::FrequencyOutOfRange => "blink frequency must be between 1 and 100 Hz",
+            ErrorCode::DurationOutOfRange => "blink duration must be between 1 second and one day",
+            ErrorCode::UnknownCommand => "the device does not know this command or id",
+            ErrorCode::CrcMismatch => "the request was corrupted in transit (CRC mismatch)",
+            ErrorCode::BufferOverflow => "the request was too large for the device receive buffer",
+            ErrorCode::AuthFailed => "the device rejected the key or a replayed request",
+            ErrorCode::InvalidTimeZone => "the time zone offset or DST rule is out of range",
+        }
+    }
+
+    /// The request never got through, re-sending it may succeed
+    ///
+    /// Such errors answer a frame the device could not read, they carry `seq = 0`.
+    pub fn is_transport(&self) -> bool {
+        matches!(self, ErrorCode::CrcMismatch | ErrorCode::BufferOverflow)
+    }
+}
+
+/// Check the blink parameters of `BlinkNow` and `BlinkAt`
+pub fn check_blink(duration_secs: u32, freq_hz: u32) -> Result<(), (ErrorCode, Detail)> {
+    if !(1..=MAX_DURATION_SECS).contains(&duration_secs) {
+        return Err((ErrorCode::DurationOutOfRange, duration_secs));
+    }
+    if !(1..=MAX_FREQ_HZ).contains(&freq_hz) {
+        return Err((ErrorCode::FrequencyOutOfRange, freq_hz));
+    }
+    Ok(())
+}
+
+/// Check an action against the device state, before anything is applied
+///
+/// `time_set` tells whether the device time is known, `BlinkAt` needs it.
+pub fn check_action(action: &Action, time_set: bool) -> Result<(), (ErrorCode, Detail)> {
+    match action {
+        Action::SetTime(dt) => check_date(dt),
+        // every `EpochNanos` is a valid time
+        Action::BlinkOff | Action::SetRgb(_) | Action::SetTimeEpoch(_) => Ok(()),
+        Action::BlinkNow(duration_secs, freq_hz) => check_blink(*duration_secs, *freq_hz),
+        Action::BlinkAt(dt, duration_secs, freq_hz) => {
+            if !time_set {
+                return Err((ErrorCode::TimeNotSet, 0));
+            }
+            check_blink(*duration_secs, *freq_hz)?;
+            check_date(dt)
+        }
+        Action::BlinkAtEpoch(_, duration_secs, freq_hz) => {
+            if !time_set {
+                return Err((ErrorCode::TimeNotSet, 0));
+            }
+            check_blink(*duration_secs, *freq_hz)
+        }
+        Action::SetTimeZone(tz) => tz.validate().map_err(|_| (ErrorCode::InvalidTimeZone, 0)),
+    }
+}
+
+fn check_date(dt: &UtcDateTime) -> Result<(), (ErrorCode, Detail)> {
+    dt.validate().map_err(|_| (ErrorCode::InvalidDate, 0))
+}
+
+#[test]
+fn blink_limits() {
+    assert_eq!(check_blink(10, 3), Ok(()));
+    assert_eq!(check_blink(MAX_DURATION_SECS, MAX_FREQ_HZ), Ok(()));
+    assert_eq!(check_blink(0, 3), Err((ErrorCode::DurationOutOfRange, 0)));
+    assert_eq!(check_blink(10, 0), Err((ErrorCode::FrequencyOutOfRange, 0)));
+    assert_eq!(
+        check_blink(10, 101),
+        Err((ErrorCode::FrequencyOutOfRange, 101))
+    );
+}
+
+#[test]
+fn action_checks() {
+    let dt = UtcDateTime {
+        year: 2023,
+        month: 2,
+        day: 29,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        nanoseconds: 0,
+    };
+    assert_eq!(
+        check_action(&Action::SetTime(dt), false),
+        Err((ErrorCode::InvalidDate, 0))
+    );
+    assert_eq!(
+        check_action(&Action::BlinkAt(dt, 10, 3), false),
+        Err((ErrorCode::TimeNotSet, 0))
+    );
+    let dt = UtcDateTime { day: 28, ..dt };
+    assert_eq!(check_action(&Action::SetTime(dt), false), Ok(()));
+    assert_eq!(check_action(&Action::BlinkAt(dt, 10, 3), true), Ok(()));
+    assert_eq!(check_action(&Action::SetRgb(true), false), Ok(()));
+    assert_eq!(check_action(&Action::SetTimeEpoch(i64::MIN), false), Ok(()));
+    assert_eq!(
+        check_action(&Action::BlinkAtEpoch(0, 10, 3), false),
+        Err((ErrorCode::TimeNotSet, 0))
+    );
+    assert_eq!(
+        check_action(&Action::BlinkAtEpoch(0, 10, 0), true),
+        Err((ErrorCode::FrequencyOutOfRange, 0))
+    );
+    let tz = TimeZone {
+        utc_offset_minutes: 24 * 60,
+        dst: None,
+    };
+    assert_eq!(
+        check_action(&Action::SetTimeZone(tz), true),
+        Err((ErrorCode::InvalidTimeZone, 0))
+    );
+    assert_eq!(
+        check_action(&Action::SetTimeZone(TimeZone::UTC), false),
+        Ok(())
+    );
+}
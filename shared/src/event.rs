@@ -0,0 +1,22 @@
+//! Unsolicited device events
+//!
+//! Sent by the device whenever something happens, not in answer to a
+//! `Command`. They travel as `Upstream::Event` so that the host can tell them
+//! from responses, with `seq = 0`.
+
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(C)]
+pub enum Event {
+    /// the blinker switched on, device time in epoch milliseconds
+    BlinkStarted(i64),
+    /// the blinker switched off, device time in epoch milliseconds
+    BlinkEnded(i64),
+    /// the device has no reference time yet, `SetTime` is needed
+    TimeNotSet,
+    /// the device (re)started, its state is back to defaults
+    Rebooted,
+    /// an oversized frame was dropped, detail is the number of bytes
+    RxOverflow(u32),
+}
@@ -0,0 +1,262 @@
+//! Deterministic fault injection for robustness testing
+//!
+//! Wraps an encoded frame (or a byte stream) and corrupts it according to a
+//! set of per-fault probabilities. The random source is a small xorshift
+//! generator, so a given seed always reproduces the same corruption.
+
+use corncobs::ZERO;
+
+/// Probability of each fault class, `0.0` (never) to `1.0` (always)
+///
+/// For `FaultInjector::corrupt_frame` the probabilities apply per frame, for
+/// `FaultInjector::corrupt_byte` `bit_flip` and `drop_byte` apply per byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultConfig {
+    /// flip a random bit
+    pub bit_flip: f32,
+    /// remove a random byte
+    pub drop_byte: f32,
+    /// cut the frame short, including its delimiter
+    pub truncate: f32,
+    /// send the frame twice
+    pub duplicate: f32,
+    /// insert a garbage frame before the frame
+    pub garbage: f32,
+}
+
+/// Number of faults injected so far, per class
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub bit_flips: u32,
+    pub dropped_bytes: u32,
+    pub truncations: u32,
+    pub duplicates: u32,
+    pub garbage_frames: u32,
+}
+
+// longest garbage frame inserted, excluding its delimiter
+const MAX_GARBAGE: usize = 8;
+
+pub struct FaultInjector {
+    state: u64,
+    config: FaultConfig,
+    stats: FaultStats,
+}
+
+impl FaultInjector {
+    pub fn new(seed: u64, config: FaultConfig) -> Self {
+        FaultInjector {
+            // xorshift gets stuck at zero
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+            config,
+            stats: FaultStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> &FaultConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &FaultStats {
+        &self.stats
+    }
+
+    /// Corrupt an encoded frame into `out`, returns the bytes to put on the wire
+    ///
+    /// `out` should hold `2 * frame.len() + 9` bytes so that every fault can be
+    /// applied, faults that do not fit are skipped.
+    pub fn corrupt_frame<'a>(&mut self, frame: &[u8], out: &'a mut [u8]) -> &'a [u8] {
+        let mut n = 0;
+
+        if self.chance(self.config.garbage) && out.len() > frame.len() + MAX_GARBAGE {
+            let len = 1 + self.below(MAX_GARBAGE);
+            for b in &mut out[..len] {
+                // non-zero, so the garbage forms a single frame
+                *b = (self.next() as u8) | 1;
+            }
+            out[len] = ZERO;
+            n = len + 1;
+            self.stats.garbage_frames += 1;
+        }
+
+        if out.len() < n + frame.len() {
+            return &out[..n];
+        }
+        let start = n;
+        out[start..start + frame.len()].copy_from_slice(frame);
+        n += frame.len();
+
+        if self.chance(self.config.bit_flip) && n > start {
+            let idx = start + self.below(n - start);
+            out[idx] ^= 1 << self.below(8);
+            self.stats.bit_flips += 1;
+        }
+
+        if self.chance(self.config.drop_byte) && n > start {
+            let idx = start + self.below(n - start);
+            out.copy_within(idx + 1..n, idx);
+            n -= 1;
+            self.stats.dropped_bytes += 1;
+        }
+
+        if self.chance(self.config.truncate) && n > start {
+            n = start + self.below(n - start);
+            self.stats.truncations += 1;
+        }
+
+        if self.chance(self.config.duplicate) && out.len() >= n + (n - start) {
+            out.copy_within(start..n, n);
+            n += n - start;
+            self.stats.duplicates += 1;
+        }
+
+        &out[..n]
+    }
+
+    /// Corrupt a single byte of a stream, `None` if the byte is dropped
+    pub fn corrupt_byte(&mut self, byte: u8) -> Option<u8> {
+        if self.chance(self.config.drop_byte) {
+            self.stats.dropped_bytes += 1;
+            return None;
+        }
+        if self.chance(self.config.bit_flip) {
+            self.stats.bit_flips += 1;
+            return Some(byte ^ (1 << self.below(8)));
+        }
+        Some(byte)
+    }
+
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // uniform in 0..n, n > 0
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, p: f32) -> bool {
+        if p <= 0.0 {
+            return false;
+        }
+        // top 24 bits give an exact f32 in [0, 1)
+        ((self.next() >> 40) as f32 / (1u32 << 24) as f32) < p
+    }
+}
+
+#[cfg(test)]
+fn recovers_after(config: FaultConfig) -> FaultStats {
+    use crate::{
+        deserialize_crc_cobs, frame_accumulator::FrameAccumulator, serialize_crc_cobs, Seq,
+    };
+
+    let mut injector = FaultInjector::new(42, config);
+    let mut acc = FrameAccumulator::<64>::new();
+    let mut tx_buf = [0u8; 32];
+    let mut wire = [0u8; 128];
+
+    for seq in 0..50 as Seq {
+        let value: u32 = 0x0100_0001 * seq as u32;
+
+        // corrupted frame first, then the same frame intact
+        let frame = serialize_crc_cobs(seq, &value, &mut tx_buf).unwrap();
+        let corrupted = injector.corrupt_frame(frame, &mut wire);
+        for &b in corrupted {
+            if let Some(f) = acc.push(b) {
+                // corruption may only ever show up as a fault or a duplicate
+                if let Ok(decoded) = deserialize_crc_cobs::<u32>(f) {
+                    assert_eq!(decoded, (seq, value));
+                }
+            }
+        }
+
+        // a delimiter re-synchronises the receiver after a truncated frame
+        acc.push(ZERO);
+        let frame = serialize_crc_cobs(seq, &value, &mut tx_buf).unwrap();
+        let mut received = None;
+        for &b in frame {
+            if let Some(f) = acc.push(b) {
+                received = Some(deserialize_crc_cobs::<u32>(f));
+            }
+        }
+        assert_eq!(received, Some(Ok((seq, value))));
+    }
+
+    *injector.stats()
+}
+
+#[test]
+fn recovers_from_bit_flips() {
+    let stats = recovers_after(FaultConfig {
+        bit_flip: 1.0,
+        ..Default::default()
+    });
+    assert_eq!(stats.bit_flips, 50);
+}
+
+#[test]
+fn recovers_from_dropped_bytes() {
+    let stats = recovers_after(FaultConfig {
+        drop_byte: 1.0,
+        ..Default::default()
+    });
+    assert_eq!(stats.dropped_bytes, 50);
+}
+
+#[test]
+fn recovers_from_truncation() {
+    let stats = recovers_after(FaultConfig {
+        truncate: 1.0,
+        ..Default::default()
+    });
+    assert_eq!(stats.truncations, 50);
+}
+
+#[test]
+fn recovers_from_duplicates() {
+    let stats = recovers_after(FaultConfig {
+        duplicate: 1.0,
+        ..Default::default()
+    });
+    assert_eq!(stats.duplicates, 50);
+}
+
+#[test]
+fn recovers_from_garbage() {
+    let stats = recovers_after(FaultConfig {
+        garbage: 1.0,
+        ..Default::default()
+    });
+    assert_eq!(stats.garbage_frames, 50);
+}
+
+#[test]
+fn same_seed_same_corruption() {
+    let config = FaultConfig {
+        bit_flip: 0.5,
+        drop_byte: 0.5,
+        truncate: 0.2,
+        duplicate: 0.2,
+        garbage: 0.2,
+    };
+    let frame = [3, 1, 2, 4, 5, 6, 7, 0];
+    let mut a = FaultInjector::new(7, config);
+    let mut b = FaultInjector::new(7, config);
+    let (mut out_a, mut out_b) = ([0u8; 32], [0u8; 32]);
+
+    for _ in 0..100 {
+        assert_eq!(
+            a.corrupt_frame(&frame, &mut out_a),
+            b.corrupt_frame(&frame, &mut out_b)
+        );
+    }
+    assert_eq!(a.stats(), b.stats());
+}
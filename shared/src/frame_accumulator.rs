@@ -0,0 +1,151 @@
+use corncobs::ZERO;
+
+/// Assembles COBS frames from a byte stream, one byte at a time
+///
+/// Bytes are collected until the `ZERO` delimiter arrives, at which point the
+/// complete frame (including the delimiter) is handed out. Frames that do not
+/// fit in `N` bytes are discarded and reception resynchronises on the next
+/// delimiter, so a noisy line can never index out of bounds.
+pub struct FrameAccumulator<const N: usize> {
+    buf: [u8; N],
+    idx: usize,
+    overflowed: bool,
+    dropped_bytes: u32,
+    dropped_frames: u32,
+}
+
+impl<const N: usize> Default for FrameAccumulator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FrameAccumulator<N> {
+    pub const fn new() -> Self {
+        FrameAccumulator {
+            buf: [0; N],
+            idx: 0,
+            overflowed: false,
+            dropped_bytes: 0,
+            dropped_frames: 0,
+        }
+    }
+
+    /// Feed one byte, returns the complete frame when `byte` is the delimiter
+    ///
+    /// The returned slice ends with the `ZERO` delimiter and can be passed
+    /// straight to `deserialize_crc_cobs`. Empty frames (back to back
+    /// delimiters) are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<&mut [u8]> {
+        if byte == ZERO {
+            let len = self.idx;
+            self.idx = 0;
+
+            if self.overflowed {
+                // the tail of an oversized frame, we are now back in sync
+                self.overflowed = false;
+                self.dropped_bytes = self.dropped_bytes.wrapping_add(1);
+                self.dropped_frames = self.dropped_frames.wrapping_add(1);
+                return None;
+            }
+
+            if len == 0 {
+                return None;
+            }
+
+            if len >= N {
+                // no room left for the delimiter itself
+                self.dropped_bytes = self.dropped_bytes.wrapping_add(len as u32 + 1);
+                self.dropped_frames = self.dropped_frames.wrapping_add(1);
+                return None;
+            }
+
+            self.buf[len] = ZERO;
+            return Some(&mut self.buf[..=len]);
+        }
+
+        if self.overflowed {
+            self.dropped_bytes = self.dropped_bytes.wrapping_add(1);
+        } else if self.idx < N {
+            self.buf[self.idx] = byte;
+            self.idx += 1;
+        } else {
+            // frame too large, drop what we have and wait for the delimiter
+            self.overflowed = true;
+            self.dropped_bytes = self.dropped_bytes.wrapping_add(self.idx as u32 + 1);
+            self.idx = 0;
+        }
+        None
+    }
+
+    /// Discard any partially received frame
+    pub fn reset(&mut self) {
+        self.idx = 0;
+        self.overflowed = false;
+    }
+
+    /// Number of bytes currently buffered for the next frame
+    pub fn pending(&self) -> usize {
+        self.idx
+    }
+
+    /// Total number of bytes discarded due to oversized frames
+    pub fn dropped_bytes(&self) -> u32 {
+        self.dropped_bytes
+    }
+
+    /// Total number of oversized frames discarded
+    pub fn dropped_frames(&self) -> u32 {
+        self.dropped_frames
+    }
+}
+
+#[test]
+fn yields_frame_on_delimiter() {
+    let mut acc = FrameAccumulator::<8>::new();
+
+    for &b in &[1, 2, 3] {
+        assert!(acc.push(b).is_none());
+    }
+    assert_eq!(acc.push(ZERO).map(|f| f.to_vec()), Some(vec![1, 2, 3, 0]));
+    assert_eq!(acc.pending(), 0);
+}
+
+#[test]
+fn skips_empty_frames() {
+    let mut acc = FrameAccumulator::<8>::new();
+
+    assert!(acc.push(ZERO).is_none());
+    assert!(acc.push(ZERO).is_none());
+    assert_eq!(acc.dropped_frames(), 0);
+}
+
+#[test]
+fn discards_oversized_frame_and_resyncs() {
+    let mut acc = FrameAccumulator::<4>::new();
+
+    for b in 1..=6 {
+        assert!(acc.push(b).is_none());
+    }
+    assert!(acc.push(ZERO).is_none());
+    assert_eq!(acc.dropped_frames(), 1);
+    assert_eq!(acc.dropped_bytes(), 7);
+
+    // next frame is received intact
+    acc.push(7);
+    acc.push(8);
+    assert_eq!(acc.push(ZERO).map(|f| f.to_vec()), Some(vec![7, 8, 0]));
+}
+
+#[test]
+fn frame_filling_buffer_is_dropped() {
+    // a frame must leave room for its delimiter
+    let mut acc = FrameAccumulator::<3>::new();
+
+    for b in 1..=3 {
+        assert!(acc.push(b).is_none());
+    }
+    assert!(acc.push(ZERO).is_none());
+    assert_eq!(acc.dropped_frames(), 1);
+    assert_eq!(acc.dropped_bytes(), 4);
+}
@@ -0,0 +1,25 @@
+//! Ids for `Command::Get(id, param, devid)`
+//!
+//! Answered with `Response::Data(id, param, value, devid)`, unknown ids and
+//! parameters with `Response::Error(ErrorCode::UnknownCommand, id)`.
+
+use crate::{Id, Parameter};
+
+/// current epoch time in milliseconds
+pub const TIME: Id = 1;
+/// 1 once the time has been set by the host, 0 otherwise
+pub const TIME_SET: Id = 2;
+/// active blink configuration, the field is selected by the parameter
+pub const BLINK_CONFIG: Id = 3;
+/// 1 if the RGB led is enabled, 0 otherwise
+pub const RGB_ACTIVE: Id = 4;
+/// milliseconds since boot
+pub const UPTIME: Id = 5;
+/// local time minus UTC in minutes, right now, see `time_zone`
+pub const UTC_OFFSET: Id = 6;
+
+// parameters for `BLINK_CONFIG`
+pub const BLINK_START: Parameter = 0; // epoch milliseconds
+pub const BLINK_END: Parameter = 1; // epoch milliseconds
+pub const BLINK_PERIOD: Parameter = 2; // milliseconds
+pub const BLINK_ACTIVE: Parameter = 3; // 1 while blinking, 0 otherwise
@@ -0,0 +1,158 @@
+//! Handshake payload and capability bitmap
+//!
+//! The host sends `Command::Hello` on connect, the device answers with
+//! `Response::Hello(HelloInfo)` describing itself.
+
+use crate::{Action, Command, DevId, Message};
+use serde_derive::{Deserialize, Serialize};
+
+/// Wire protocol version, bumped on every incompatible change
+pub const PROTOCOL_VERSION: u16 = 8;
+
+pub type Capabilities = u32;
+
+// one bit per command the device understands
+pub const CAP_SET_TIME: Capabilities = 1 << 0; // Set(1, Message::A)
+pub const CAP_BLINK_OFF: Capabilities = 1 << 1; // Set(2, Message::B)
+pub const CAP_BLINK_NOW: Capabilities = 1 << 2; // Set(3, Message::C)
+pub const CAP_BLINK_AT: Capabilities = 1 << 3; // Set(4, Message::D)
+pub const CAP_SET_RGB: Capabilities = 1 << 4; // Set(5, Message::B)
+pub const CAP_GET: Capabilities = 1 << 5;
+pub const CAP_HELLO: Capabilities = 1 << 6;
+pub const CAP_ACTION: Capabilities = 1 << 7; // Command::Action encoding
+pub const CAP_EVENTS: Capabilities = 1 << 8; // sends `Upstream::Event` frames
+pub const CAP_BATCH: Capabilities = 1 << 9; // Command::Batch
+pub const CAP_EPOCH_TIME: Capabilities = 1 << 10; // Message::E and F, SetTimeEpoch and BlinkAtEpoch
+pub const CAP_TIME_ZONE: Capabilities = 1 << 11; // Set(6, Message::G)
+
+pub const FW_VERSION_LEN: usize = 16;
+pub const GIT_HASH_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HelloInfo {
+    pub protocol_version: u16,
+    /// firmware crate version, ASCII, zero padded
+    pub fw_version: [u8; FW_VERSION_LEN],
+    /// short git hash of the firmware build, ASCII, zero padded
+    pub git_hash: [u8; GIT_HASH_LEN],
+    pub dev_id: DevId,
+    pub capabilities: Capabilities,
+}
+
+impl HelloInfo {
+    pub fn fw_version(&self) -> &str {
+        padded_str(&self.fw_version)
+    }
+
+    pub fn git_hash(&self) -> &str {
+        padded_str(&self.git_hash)
+    }
+
+    /// true if the device advertises support for `cmd`
+    pub fn supports(&self, cmd: &Command) -> bool {
+        match required_capability(cmd) {
+            Some(cap) => self.capabilities & cap == cap,
+            None => false,
+        }
+    }
+}
+
+/// Capability bit needed to execute `cmd`, `None` for ids no firmware knows about
+pub fn required_capability(cmd: &Command) -> Option<Capabilities> {
+    match cmd {
+        Command::Set(1, Message::E(_), _) => Some(CAP_SET_TIME | CAP_EPOCH_TIME),
+        Command::Set(4, Message::F(..), _) => Some(CAP_BLINK_AT | CAP_EPOCH_TIME),
+        Command::Set(1, _, _) => Some(CAP_SET_TIME),
+        Command::Set(2, _, _) => Some(CAP_BLINK_OFF),
+        Command::Set(3, _, _) => Some(CAP_BLINK_NOW),
+        Command::Set(4, _, _) => Some(CAP_BLINK_AT),
+        Command::Set(5, _, _) => Some(CAP_SET_RGB),
+        Command::Set(6, _, _) => Some(CAP_TIME_ZONE),
+        Command::Set(_, _, _) => None,
+        Command::Get(_, _, _) => Some(CAP_GET),
+        Command::Hello(_) => Some(CAP_HELLO),
+        Command::Action(action, _) => Some(CAP_ACTION | action_capability(action)),
+        Command::Batch(batch, _) => Some(
+            batch
+                .iter()
+                .flatten()
+                .fold(CAP_BATCH, |caps, action| caps | action_capability(action)),
+        ),
+    }
+}
+
+/// Capability bit of an `Action`, shared with its legacy `Set` form
+pub fn action_capability(action: &Action) -> Capabilities {
+    match action {
+        Action::SetTime(_) => CAP_SET_TIME,
+        Action::BlinkOff => CAP_BLINK_OFF,
+        Action::BlinkNow(_, _) => CAP_BLINK_NOW,
+        Action::BlinkAt(_, _, _) => CAP_BLINK_AT,
+        Action::SetRgb(_) => CAP_SET_RGB,
+        Action::SetTimeEpoch(_) => CAP_SET_TIME | CAP_EPOCH_TIME,
+        Action::BlinkAtEpoch(..) => CAP_BLINK_AT | CAP_EPOCH_TIME,
+        Action::SetTimeZone(_) => CAP_TIME_ZONE,
+    }
+}
+
+/// Copy `s` into a zero padded array, truncating if it does not fit
+///
+/// `const` so that build time strings such as `env!("CARGO_PKG_VERSION")`
+/// can be turned into `HelloInfo` fields without any runtime cost.
+pub const fn padded<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    let mut out = [0u8; N];
+    let mut i = 0;
+    while i < N && i < bytes.len() {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+fn padded_str(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len]).unwrap_or("<invalid>")
+}
+
+#[test]
+fn padded_roundtrip() {
+    let info = HelloInfo {
+        protocol_version: PROTOCOL_VERSION,
+        fw_version: padded("0.1.0"),
+        git_hash: padded("0123456789abcdef"),
+        dev_id: 1,
+        capabilities: CAP_SET_TIME | CAP_HELLO,
+    };
+
+    assert_eq!(info.fw_version(), "0.1.0");
+    assert_eq!(info.git_hash(), "01234567");
+    assert!(info.supports(&Command::Hello(1)));
+    assert!(!info.supports(&Command::Get(0, 0, 1)));
+    assert!(!info.supports(&Command::Set(9, crate::Message::B(0), 1)));
+    assert!(!info.supports(&Command::Action(Action::BlinkOff, 1)));
+
+    // a batch needs the batch bit and the bit of every action in it
+    let info = HelloInfo {
+        capabilities: CAP_BATCH | CAP_SET_RGB,
+        ..info
+    };
+    let batch =
+        |actions: &[Action]| Command::Batch(crate::batch::from_actions(actions).unwrap(), 1);
+    assert!(info.supports(&batch(&[Action::SetRgb(true)])));
+    assert!(!info.supports(&batch(&[Action::SetRgb(true), Action::BlinkOff])));
+
+    // the epoch forms need their own bit on top of the calendar one
+    let info = HelloInfo {
+        capabilities: CAP_ACTION | CAP_SET_TIME,
+        ..info
+    };
+    assert!(!info.supports(&Command::Action(Action::SetTimeEpoch(0), 1)));
+    assert!(!info.supports(&Command::Set(1, Message::E(0), 1)));
+    let info = HelloInfo {
+        capabilities: CAP_ACTION | CAP_SET_TIME | CAP_EPOCH_TIME,
+        ..info
+    };
+    assert!(info.supports(&Command::Action(Action::SetTimeEpoch(0), 1)));
+    assert!(info.supports(&Command::Set(1, Message::E(0), 1)));
+}
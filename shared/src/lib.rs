@@ -1,87 +1,411 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
+pub mod address;
+#[cfg(feature = "auth")]
+pub mod auth;
+pub mod batch;
 pub mod date_time;
+pub mod dedup;
+pub mod encoded_size;
+pub mod encoder;
+pub mod error;
+pub mod event;
+#[cfg(feature = "fault-injection")]
+pub mod fault_injector;
+pub mod frame_accumulator;
+pub mod get;
+pub mod hello;
 pub mod shift_register;
+pub mod tempo;
+pub mod time_zone;
+pub mod wire;
 
-use date_time::UtcDateTime;
+use batch::Batch;
+use date_time::{EpochNanos, UtcDateTime};
+use error::{Detail, ErrorCode};
+use event::Event;
+use hello::HelloInfo;
 use serde_derive::{Deserialize, Serialize};
+use time_zone::TimeZone;
 
 // we could use new-type pattern here but let's keep it simple
 pub type Id = u32;
 pub type DevId = u32;
 pub type Parameter = u32;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Per-frame sequence number, a `Response` echoes the `Seq` of the `Command` it answers
+pub type Seq = u16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[repr(C)]
 pub enum Command {
     Set(Id, Message, DevId),
     Get(Id, Parameter, DevId),
+    Hello(DevId),
+    Action(Action, DevId),
+    /// actions applied all together or not at all, see `batch`
+    Batch(Batch, DevId),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[repr(C)]
 pub enum Message {
     A(UtcDateTime),
     B(u32),
     C(u32, u32), // we might consider "f16" but not sure it plays well with `ssmarshal`
     D(UtcDateTime, u32, u32),
+    /// `A` as an epoch timestamp
+    E(EpochNanos),
+    /// `D` as an epoch timestamp
+    F(EpochNanos, u32, u32),
+    G(TimeZone),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Typed command vocabulary, sent as `Command::Action`
+///
+/// Replaces the `Id` + `Message` pairings of `Command::Set`, where only some
+/// combinations are meaningful. The legacy encoding maps as follows:
+///
+/// | legacy `Set`                    | `Action`                                 |
+/// | ------------------------------- | ---------------------------------------- |
+/// | `Set(1, Message::A(dt), _)`     | `SetTime(dt)`                            |
+/// | `Set(2, Message::B(_), _)`      | `BlinkOff`                               |
+/// | `Set(3, Message::C(dur, hz), _)`| `BlinkNow(dur, hz)`                      |
+/// | `Set(4, Message::D(dt, dur, hz), _)` | `BlinkAt(dt, dur, hz)`              |
+/// | `Set(5, Message::B(v), _)`      | `SetRgb(v != 0)`                         |
+/// | `Set(1, Message::E(ns), _)`     | `SetTimeEpoch(ns)`                       |
+/// | `Set(4, Message::F(ns, dur, hz), _)` | `BlinkAtEpoch(ns, dur, hz)`         |
+/// | `Set(6, Message::G(tz), _)`     | `SetTimeZone(tz)`                        |
+///
+/// Any other pairing is rejected by `Action::try_from`.
+///
+/// `SetTimeEpoch` and `BlinkAtEpoch` carry the time as one `EpochNanos`, the
+/// frame is shorter and the device needs no calendar arithmetic to use it.
+/// Only devices advertising `hello::CAP_EPOCH_TIME` take them, see
+/// `into_epoch` and `into_calendar` for the conversions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub enum Action {
+    /// set the device reference date and time
+    SetTime(UtcDateTime),
+    /// turn the blinker off right now
+    BlinkOff,
+    /// blink right now for (duration_secs, frequency_hz)
+    BlinkNow(u32, u32),
+    /// blink at a given time for (duration_secs, frequency_hz)
+    BlinkAt(UtcDateTime, u32, u32),
+    /// turn the RGB led on or off
+    SetRgb(bool),
+    /// `SetTime` as an epoch timestamp
+    SetTimeEpoch(EpochNanos),
+    /// `BlinkAt` as an epoch timestamp
+    BlinkAtEpoch(EpochNanos, u32, u32),
+    /// set the time zone the device shows local time in, see `time_zone`
+    SetTimeZone(TimeZone),
+}
+
+impl Action {
+    /// `SetTime` and `BlinkAt` as epoch timestamps, other actions and dates
+    /// that do not fit an `EpochNanos` unchanged
+    pub fn into_epoch(self) -> Self {
+        match self {
+            Action::SetTime(dt) => match dt.to_epoch_nanos() {
+                Ok(nanos) => Action::SetTimeEpoch(nanos),
+                Err(_) => self,
+            },
+            Action::BlinkAt(dt, duration_secs, freq_hz) => match dt.to_epoch_nanos() {
+                Ok(nanos) => Action::BlinkAtEpoch(nanos, duration_secs, freq_hz),
+                Err(_) => self,
+            },
+            other => other,
+        }
+    }
+
+    /// `SetTimeEpoch` and `BlinkAtEpoch` with calendar fields, for devices
+    /// without `hello::CAP_EPOCH_TIME`
+    pub fn into_calendar(self) -> Self {
+        match self {
+            Action::SetTimeEpoch(nanos) => Action::SetTime(UtcDateTime::from_epoch_nanos(nanos)),
+            Action::BlinkAtEpoch(nanos, duration_secs, freq_hz) => {
+                Action::BlinkAt(UtcDateTime::from_epoch_nanos(nanos), duration_secs, freq_hz)
+            }
+            other => other,
+        }
+    }
+}
+
+impl TryFrom<(Id, Message)> for Action {
+    type Error = (Id, Message);
+
+    /// Decode a legacy `Command::Set(id, message, _)`, see the table on `Action`
+    fn try_from((id, msg): (Id, Message)) -> Result<Self, Self::Error> {
+        match (id, msg) {
+            (1, Message::A(dt)) => Ok(Action::SetTime(dt)),
+            (2, Message::B(_)) => Ok(Action::BlinkOff),
+            (3, Message::C(duration_secs, freq_hz)) => Ok(Action::BlinkNow(duration_secs, freq_hz)),
+            (4, Message::D(dt, duration_secs, freq_hz)) => {
+                Ok(Action::BlinkAt(dt, duration_secs, freq_hz))
+            }
+            (5, Message::B(state)) => Ok(Action::SetRgb(state != 0)),
+            (1, Message::E(nanos)) => Ok(Action::SetTimeEpoch(nanos)),
+            (4, Message::F(nanos, duration_secs, freq_hz)) => {
+                Ok(Action::BlinkAtEpoch(nanos, duration_secs, freq_hz))
+            }
+            (6, Message::G(tz)) => Ok(Action::SetTimeZone(tz)),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<Action> for (Id, Message) {
+    /// Encode as a legacy `Command::Set(id, message, _)` for older firmware
+    fn from(action: Action) -> Self {
+        match action {
+            Action::SetTime(dt) => (1, Message::A(dt)),
+            Action::BlinkOff => (2, Message::B(0)),
+            Action::BlinkNow(duration_secs, freq_hz) => (3, Message::C(duration_secs, freq_hz)),
+            Action::BlinkAt(dt, duration_secs, freq_hz) => {
+                (4, Message::D(dt, duration_secs, freq_hz))
+            }
+            Action::SetRgb(state) => (5, Message::B(state as u32)),
+            Action::SetTimeEpoch(nanos) => (1, Message::E(nanos)),
+            Action::BlinkAtEpoch(nanos, duration_secs, freq_hz) => {
+                (4, Message::F(nanos, duration_secs, freq_hz))
+            }
+            Action::SetTimeZone(tz) => (6, Message::G(tz)),
+        }
+    }
+}
+
+impl Command {
+    /// Every `SetTime` and `BlinkAt` as an epoch timestamp, see `Action::into_epoch`
+    pub fn into_epoch(self) -> Self {
+        let epoch = |(id, msg): (Id, Message)| match Action::try_from((id, msg)) {
+            Ok(action) => action.into_epoch().into(),
+            Err(legacy) => legacy,
+        };
+        match self {
+            Command::Set(id, msg, dev) => {
+                let (id, msg) = epoch((id, msg));
+                Command::Set(id, msg, dev)
+            }
+            Command::Action(action, dev) => Command::Action(action.into_epoch(), dev),
+            Command::Batch(batch, dev) => {
+                Command::Batch(batch.map(|action| action.map(Action::into_epoch)), dev)
+            }
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[repr(C)]
 pub enum Response {
-    Data(Id, Parameter, u32, DevId),
+    Data(Id, Parameter, i64, DevId), // see `get` for the ids
     SetOk,
     ParseError,
-    NotOK,
-    Illegal,
+    NotOK,   // superseded by `Error(ErrorCode::CrcMismatch, _)`
+    Illegal, // superseded by the specific `Error` codes
+    Hello(HelloInfo),
+    Error(ErrorCode, Detail),
+    /// nothing of a `Command::Batch` was applied, the action at the index was rejected
+    BatchRejected(u8, ErrorCode, Detail),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Everything the device sends, tagged so that events and responses can be told apart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub enum Upstream {
+    /// answer to the `Command` with the same `Seq`
+    Response(Response),
+    /// unsolicited, sent with `seq = 0` by the device with the given address
+    Event(Event, DevId),
+}
+
+/// Errors reported by the framing layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(C)]
 pub enum Faults {
+    /// CRC mismatch, the payload or CRC was corrupted in transit
     BitFlipData,
+    /// COBS decoding failed, the frame contained an unexpected zero
+    CobsDecode,
+    /// The frame ended before the payload and CRC were complete
+    Truncated,
+    /// The payload could not be deserialized into the expected type
+    Deserialize,
+    /// The output buffer cannot hold the encoded frame
+    BufferTooSmall,
+    /// Intact frame with a wrong MAC or a counter already seen, see `auth`
+    AuthFailed,
+}
+
+impl From<corncobs::CobsError> for Faults {
+    fn from(e: corncobs::CobsError) -> Self {
+        match e {
+            corncobs::CobsError::Truncated => Faults::Truncated,
+            corncobs::CobsError::Corrupt => Faults::CobsDecode,
+        }
+    }
+}
+
+impl From<ssmarshal::Error> for Faults {
+    fn from(e: ssmarshal::Error) -> Self {
+        match e {
+            ssmarshal::Error::EndOfStream => Faults::Truncated,
+            _ => Faults::Deserialize,
+        }
+    }
+}
+
+impl From<Faults> for Response {
+    /// CRC mismatches are reported as `Error(CrcMismatch)`, authentication
+    /// failures as `Error(AuthFailed)`, everything else as `ParseError`
+    fn from(fault: Faults) -> Self {
+        match fault {
+            Faults::BitFlipData => Response::Error(ErrorCode::CrcMismatch, 0),
+            Faults::AuthFailed => Response::Error(ErrorCode::AuthFailed, 0),
+            _ => Response::ParseError,
+        }
+    }
 }
 
 pub const CKSUM: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_CKSUM);
 
-/// Serialize T into cobs encoded out_buf with crc
-/// panics on all errors
-/// TODO: reasonable error handling
+/// Bytes of the CRC trailer, little endian whatever the `wire` backend
+pub const CRC_LEN: usize = 4;
+
+/// Serialize seq and T into cobs encoded out_buf with crc
+/// returns `Faults::BufferTooSmall` if out_buf cannot hold the frame
+///
+/// Frame layout before COBS encoding: `seq | payload | crc(seq | payload)`,
+/// `seq` and payload encoded by the `wire` backend
+///
+/// Encodes in place, see `encoder::serialize_crc_cobs_to` for writing
+/// straight to a sink without a frame sized buffer.
 pub fn serialize_crc_cobs<'a, T: serde::Serialize, const N: usize>(
+    seq: Seq,
     t: &T,
     out_buf: &'a mut [u8; N],
-    test_mode : bool,
-) -> &'a [u8] {
-    let n_ser = ssmarshal::serialize(out_buf, t).unwrap();
-    let mut crc = CKSUM.checksum(&out_buf[0..n_ser]);
-    
-    if test_mode == true {
-        crc = crc + 1;
-    }
-    
-    let n_crc = ssmarshal::serialize(&mut out_buf[n_ser..], &crc).unwrap();
-    let buf_copy = *out_buf; // implies memcpy, could we do better?
-    let n = corncobs::encode_buf(&buf_copy[0..n_ser + n_crc], out_buf);
-    &out_buf[0..n]
+) -> Result<&'a [u8], Faults> {
+    let n = encoder::encode_in_place(seq, t, out_buf)?;
+    Ok(&out_buf[0..n])
 }
 
-/// deserialize T from cobs in_buf with crc check
-/// panics on all errors
-pub fn deserialize_crc_cobs<T>(in_buf: &mut [u8]) -> Result<T, Faults>
+/// deserialize seq and T from cobs in_buf with crc check
+/// malformed frames are reported as `Faults`, never panics
+///
+/// ssmarshal flags running out of input with a `debug_assert!` before it
+/// returns the error, so a debug build would still panic on a truncated
+/// frame. Every crate linking this one therefore sets
+/// `[profile.dev.package.ssmarshal] debug-assertions = false`, profiles are
+/// only read from the manifest of the crate being built.
+pub fn deserialize_crc_cobs<T>(in_buf: &mut [u8]) -> Result<(Seq, T), Faults>
 where
     T: for<'de> serde::Deserialize<'de>,
 {
-    let n = corncobs::decode_in_place(in_buf).unwrap();
-    let (t, resp_used) = ssmarshal::deserialize::<T>(&in_buf[0..n]).unwrap();
-    let crc_buf = &in_buf[resp_used..];
-    let (crc, _crc_used) = ssmarshal::deserialize::<u32>(crc_buf).unwrap();
-    let pkg_crc = CKSUM.checksum(&in_buf[0..resp_used]);
+    let n = corncobs::decode_in_place(in_buf)?;
+    if n < CRC_LEN {
+        return Err(Faults::Truncated);
+    }
+    let payload_len = n - CRC_LEN;
+    let ((seq, t), resp_used) = wire::deserialize::<(Seq, T)>(&in_buf[0..payload_len])?;
+    // a corrupted code byte can pull the delimiter into the frame as data
+    if resp_used != payload_len {
+        return Err(Faults::Deserialize);
+    }
+    let mut crc = [0; CRC_LEN];
+    crc.copy_from_slice(&in_buf[payload_len..n]);
+    let pkg_crc = CKSUM.checksum(&in_buf[0..payload_len]);
 
     // check for bitflip within payload/CRC
-    if crc != pkg_crc {
+    if u32::from_le_bytes(crc) != pkg_crc {
         return Err(Faults::BitFlipData);
     }
 
-    Ok(t)
+    Ok((seq, t))
+}
+
+#[test]
+fn legacy_set_maps_to_action() {
+    let dt = UtcDateTime {
+        year: 2023,
+        month: 10,
+        day: 17,
+        hour: 14,
+        minute: 30,
+        second: 0,
+        nanoseconds: 0,
+    };
+
+    for action in [
+        Action::SetTime(dt),
+        Action::BlinkOff,
+        Action::BlinkNow(10, 3),
+        Action::BlinkAt(dt, 10, 6),
+        Action::SetRgb(true),
+        Action::SetRgb(false),
+        Action::SetTimeEpoch(1_697_553_000_000_000_000),
+        Action::BlinkAtEpoch(1_697_553_000_000_000_000, 10, 6),
+        Action::SetTimeZone(TimeZone::UTC),
+    ] {
+        let legacy: (Id, Message) = action.clone().into();
+        let back = Action::try_from(legacy).unwrap();
+        assert_eq!(back, action);
+    }
+
+    // pairings that never meant anything
+    assert!(Action::try_from((1, Message::B(0))).is_err());
+    assert!(Action::try_from((4, Message::A(dt))).is_err());
+    assert!(Action::try_from((6, Message::B(0))).is_err());
+    assert!(Action::try_from((4, Message::E(0))).is_err());
+}
+
+#[test]
+fn epoch_and_calendar_forms_convert() {
+    let dt = UtcDateTime {
+        year: 2023,
+        month: 10,
+        day: 17,
+        hour: 14,
+        minute: 30,
+        second: 0,
+        nanoseconds: 5,
+    };
+    let nanos = 1_697_553_000_000_000_005;
+
+    assert_eq!(
+        Action::SetTime(dt).into_epoch(),
+        Action::SetTimeEpoch(nanos)
+    );
+    assert_eq!(
+        Action::BlinkAt(dt, 10, 6).into_epoch(),
+        Action::BlinkAtEpoch(nanos, 10, 6)
+    );
+    assert_eq!(
+        Action::SetTimeEpoch(nanos).into_calendar(),
+        Action::SetTime(dt)
+    );
+    assert_eq!(Action::BlinkOff.into_epoch(), Action::BlinkOff);
+
+    // outside the range of `EpochNanos` the calendar form stays
+    let far = UtcDateTime { year: 2300, ..dt };
+    assert_eq!(Action::SetTime(far).into_epoch(), Action::SetTime(far));
+
+    assert_eq!(
+        Command::Set(4, Message::D(dt, 10, 6), 1).into_epoch(),
+        Command::Set(4, Message::F(nanos, 10, 6), 1)
+    );
+    assert_eq!(
+        Command::Set(9, Message::A(dt), 1).into_epoch(),
+        Command::Set(9, Message::A(dt), 1)
+    );
+    let batch = batch::from_actions(&[Action::SetTime(dt), Action::SetRgb(true)]).unwrap();
+    assert_eq!(
+        Command::Batch(batch, 1).into_epoch(),
+        Command::Batch(
+            batch::from_actions(&[Action::SetTimeEpoch(nanos), Action::SetRgb(true)]).unwrap(),
+            1
+        )
+    );
 }
@@ -1,71 +1,147 @@
-#![allow(dead_code)]
-#[derive(PartialEq)]
-pub struct ShiftRegister {
-    reg : [u64;5],
-    entries : u32,
+use core::iter::Sum;
+use core::ops::Div;
+
+/// The last `N` values inserted, newest first
+///
+/// A ring buffer, `insert` overwrites the oldest entry once the register is
+/// full. Slots that have not been written yet read back as `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftRegister<T, const N: usize> {
+    reg: [Option<T>; N],
+    // slot the next insert goes to, the oldest entry once full
+    head: usize,
+    entries: usize,
+}
 
+impl<T: Copy, const N: usize> Default for ShiftRegister<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl ShiftRegister {
+impl<T: Copy, const N: usize> ShiftRegister<T, N> {
+    const NOT_EMPTY: () = assert!(N > 0, "a ShiftRegister needs at least one slot");
 
-    pub fn new() -> Self {
-        ShiftRegister { reg: [0;5], entries: 0u32 }
+    pub const fn new() -> Self {
+        let () = Self::NOT_EMPTY;
+        ShiftRegister {
+            reg: [None; N],
+            head: 0,
+            entries: 0,
+        }
     }
 
-    pub fn insert(&mut self, val: u64) {
-        for idx in 1..(self.reg.len()-1) {
-            self.reg[idx] = self.reg[idx-1]; 
+    /// Shift `val` in, dropping the oldest entry if the register is full
+    pub fn insert(&mut self, val: T) {
+        self.reg[self.head] = Some(val);
+        self.head = (self.head + 1) % N;
+
+        if self.entries < N {
+            self.entries += 1;
         }
+    }
 
-        self.reg[0] = val;
+    /// Number of values inserted since `new` or `clear`, at most `N`
+    pub const fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Report if the shift register has been fully populated
+    pub const fn is_full(&self) -> bool {
+        self.entries == N
+    }
 
-        if self.entries < self.reg.len() as u32 {
-            self.entries = self.entries + 1;
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// The entry inserted `age` inserts ago, 0 being the newest
+    pub fn get(&self, age: usize) -> Option<T> {
+        if age >= self.entries {
+            return None;
         }
+        self.reg[(self.head + N - 1 - age) % N]
     }
-    
-    pub fn avg(&mut self) -> u64 {
-        self.reg.iter().sum::<u64>()/(self.reg.len() as u64)
+
+    /// Every slot, newest first, `None` for those not written yet
+    pub fn as_array(&self) -> [Option<T>; N] {
+        core::array::from_fn(|age| self.get(age))
     }
 
-    // report if shift register has been fully populated
-    pub fn valid_entries(&self) -> bool {
-        self.entries >= self.reg.len() as u32
+    /// The valid entries, newest first
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.entries).filter_map(|age| self.get(age))
     }
+}
 
-    /// Don't use this. It's for testing :-)
-    fn as_array(&self) -> &[Option<u64>] {
-        todo!()
+impl<T, const N: usize> ShiftRegister<T, N>
+where
+    T: Copy + Sum<T> + Div<Output = T> + TryFrom<usize>,
+{
+    /// Mean of the valid entries, `None` while the register is empty
+    pub fn avg(&self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let count = T::try_from(self.entries).ok()?;
+        Some(self.iter().sum::<T>() / count)
     }
 }
 
 #[test]
 fn insert_works() {
-    let mut sr = ShiftRegister::new();
+    let mut sr = ShiftRegister::<u64, 3>::new();
 
     sr.insert(1);
-    assert_eq!(sr.as_array(), &[Some(1), None, None]);
+    assert_eq!(sr.as_array(), [Some(1), None, None]);
 
     sr.insert(2);
-    assert_eq!(sr.as_array(), &[Some(2), Some(1), None]);
+    assert_eq!(sr.as_array(), [Some(2), Some(1), None]);
 
     sr.insert(3);
-    assert_eq!(sr.as_array(), &[Some(3), Some(2), Some(1)]);
+    assert_eq!(sr.as_array(), [Some(3), Some(2), Some(1)]);
 
     sr.insert(4);
-    assert_eq!(sr.as_array(), &[Some(4), Some(3), Some(2)]);
+    assert_eq!(sr.as_array(), [Some(4), Some(3), Some(2)]);
 }
 
+#[test]
 fn avg_works() {
-    let mut sr = ShiftRegister::new();
+    let mut sr = ShiftRegister::<u64, 3>::new();
+    assert_eq!(sr.avg(), None);
 
     sr.insert(1);
     sr.insert(2);
     sr.insert(3);
 
-    assert_eq!(sr.avg(), 1);
+    assert_eq!(sr.avg(), Some(2));
 
     sr.insert(4);
 
-    assert_eq!(sr.avg(), 1);
+    assert_eq!(sr.avg(), Some(3));
+}
+
+#[test]
+fn len_and_clear() {
+    let mut sr = ShiftRegister::<u32, 2>::default();
+    assert!(sr.is_empty() && !sr.is_full());
+
+    sr.insert(7);
+    assert_eq!((sr.len(), sr.is_full()), (1, false));
+    // only the valid entries count towards the average
+    assert_eq!(sr.avg(), Some(7));
+
+    sr.insert(8);
+    sr.insert(9);
+    assert_eq!((sr.len(), sr.is_full()), (2, true));
+    assert!(sr.iter().eq([9, 8]));
+    assert_eq!(sr.get(2), None);
+
+    sr.clear();
+    assert_eq!(sr, ShiftRegister::new());
+    assert_eq!(sr.as_array(), [None, None]);
 }
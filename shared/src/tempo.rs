@@ -0,0 +1,187 @@
+//! Tap tempo from button press timestamps
+//!
+//! `TempoEstimator` takes the raw `SystemTimer` tick count of each press and
+//! keeps the last `N` intervals between them. A single missed or doubled
+//! press gives an interval far from the others, it is rejected against the
+//! median of the accepted ones instead of dragging the average along. A press
+//! that comes too early is taken as a bounce, the next interval is measured
+//! from the press before it. Several rejections in a row mean the tempo
+//! really changed, the history then starts over from the latest interval. So
+//! does a pause longer than the reset time.
+
+use crate::shift_register::ShiftRegister;
+
+/// `SystemTimer` tick rate on the ESP32-C3
+pub const TICKS_PER_SECOND: u64 = 16_000_000;
+pub const TICKS_PER_MILLI: u64 = TICKS_PER_SECOND / 1000;
+
+/// Accepted deviation from the median interval, in percent
+pub const DEFAULT_TOLERANCE_PERCENT: u32 = 20;
+/// A pause this long starts a new tempo, 30 BPM is the slowest one
+pub const DEFAULT_RESET_MILLIS: u32 = 2000;
+/// Rejected intervals in a row taken as a change of tempo
+pub const MAX_MISSES: u8 = 3;
+
+pub const fn ticks_to_millis(ticks: u64) -> u64 {
+    ticks / TICKS_PER_MILLI
+}
+
+pub const fn millis_to_ticks(millis: u64) -> u64 {
+    millis * TICKS_PER_MILLI
+}
+
+/// Current estimate of a `TempoEstimator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tempo {
+    /// Mean of the accepted intervals
+    pub period_ticks: u64,
+    /// 0..=100, grows as the history fills up and with steadier taps
+    pub confidence: u8,
+}
+
+impl Tempo {
+    pub const fn period_millis(&self) -> u64 {
+        ticks_to_millis(self.period_ticks)
+    }
+
+    pub fn bpm(&self) -> f32 {
+        60.0 * TICKS_PER_SECOND as f32 / self.period_ticks as f32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TempoEstimator<const N: usize = 8> {
+    intervals: ShiftRegister<u64, N>,
+    last_tap: Option<u64>,
+    misses: u8,
+    // a press was ignored as a bounce since `last_tap`
+    bounced: bool,
+    tolerance_percent: u32,
+    reset_ticks: u64,
+}
+
+impl<const N: usize> Default for TempoEstimator<N> {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOLERANCE_PERCENT, DEFAULT_RESET_MILLIS)
+    }
+}
+
+impl<const N: usize> TempoEstimator<N> {
+    pub const fn new(tolerance_percent: u32, reset_millis: u32) -> Self {
+        TempoEstimator {
+            intervals: ShiftRegister::new(),
+            last_tap: None,
+            misses: 0,
+            bounced: false,
+            tolerance_percent,
+            reset_ticks: millis_to_ticks(reset_millis as u64),
+        }
+    }
+
+    /// Forget all taps
+    pub fn reset(&mut self) {
+        self.intervals.clear();
+        self.last_tap = None;
+        self.misses = 0;
+        self.bounced = false;
+    }
+
+    /// Record a press at `ticks`, returns the estimate after it
+    ///
+    /// `None` until two presses in a row less than the reset time apart have
+    /// been seen. A timestamp going backwards starts over, as after a pause.
+    pub fn tap(&mut self, ticks: u64) -> Option<Tempo> {
+        let interval = match self.last_tap.and_then(|last| ticks.checked_sub(last)) {
+            Some(interval) if interval > 0 && interval <= self.reset_ticks => interval,
+            _ => {
+                self.intervals.clear();
+                self.last_tap = Some(ticks);
+                self.misses = 0;
+                self.bounced = false;
+                return None;
+            }
+        };
+
+        if self.accepts(interval) {
+            self.intervals.insert(interval);
+            self.last_tap = Some(ticks);
+            // the interval after a bounce does not vouch for the tempo, taps
+            // at double speed would alternate between the two forever
+            if !self.bounced {
+                self.misses = 0;
+            }
+            self.bounced = false;
+        } else if self.misses + 1 >= MAX_MISSES {
+            self.intervals.clear();
+            self.intervals.insert(interval);
+            self.last_tap = Some(ticks);
+            self.misses = 0;
+            self.bounced = false;
+        } else {
+            self.misses += 1;
+            if self.too_short(interval) {
+                // keep measuring from the real press
+                self.bounced = true;
+            } else {
+                self.last_tap = Some(ticks);
+            }
+        }
+        self.tempo()
+    }
+
+    /// The estimate from the accepted intervals, `None` before the first one
+    pub fn tempo(&self) -> Option<Tempo> {
+        let count = self.intervals.len() as u64;
+        let period_ticks = self.intervals.avg()?;
+        let median = self.median()?;
+
+        // mean absolute deviation from the median, relative to the tolerance
+        let deviation = self
+            .intervals
+            .iter()
+            .map(|i| i.abs_diff(median))
+            .sum::<u64>()
+            / count;
+        let allowed = (median * self.tolerance_percent as u64 / 100).max(1);
+        let steadiness = 100 - (deviation * 100 / allowed).min(100);
+        let fill = count * 100 / N as u64;
+
+        Some(Tempo {
+            period_ticks,
+            confidence: (fill * steadiness / 100) as u8,
+        })
+    }
+
+    fn accepts(&self, interval: u64) -> bool {
+        match self.median() {
+            Some(median) => {
+                interval.abs_diff(median) * 100 <= median * self.tolerance_percent as u64
+            }
+            None => true,
+        }
+    }
+
+    fn too_short(&self, interval: u64) -> bool {
+        match self.median() {
+            Some(median) => {
+                interval * 100 < median * (100 - self.tolerance_percent.min(100) as u64)
+            }
+            None => false,
+        }
+    }
+
+    fn median(&self) -> Option<u64> {
+        let mut sorted = [0u64; N];
+        let len = self.intervals.len();
+        for (slot, interval) in sorted.iter_mut().zip(self.intervals.iter()) {
+            *slot = interval;
+        }
+        let sorted = &mut sorted[..len];
+        sorted.sort_unstable();
+        match len {
+            0 => None,
+            _ if len % 2 == 1 => Some(sorted[len / 2]),
+            _ => Some((sorted[len / 2 - 1] + sorted[len / 2]) / 2),
+        }
+    }
+}
@@ -0,0 +1,526 @@
+//! Local time on the device, a UTC offset and optionally a DST rule
+//!
+//! The device clock runs in UTC. `TimeZone` says how far local wall-clock
+//! time is ahead of it, at any instant, so that features such as the colour
+//! schedule can follow local time. Daylight saving time follows a rule like
+//! that of a POSIX TZ string, `EET-2EEST,M3.5.0/3,M10.5.0/4` for Finland:
+//! DST starts on the last Sunday of March at 03:00 standard time and ends on
+//! the last Sunday of October at 04:00 daylight time.
+//!
+//! With the `std` feature a `TimeZone` parses from such a string. The names
+//! are only checked, the device has no use for them.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use core::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::date_time::{civil_from_days, days_from_civil, days_in_month};
+
+/// Largest offset from UTC, standard or daylight, just under a day
+pub const MAX_OFFSET_MINUTES: i16 = 24 * 60 - 1;
+
+const MILLIS_PER_MINUTE: i64 = 60_000;
+const MILLIS_PER_DAY: i64 = 24 * 60 * MILLIS_PER_MINUTE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeZone {
+    /// local standard time minus UTC in minutes, positive east of Greenwich
+    pub utc_offset_minutes: i16,
+    /// daylight saving time, `None` for a fixed offset
+    pub dst: Option<DstRule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DstRule {
+    /// minutes added to the standard offset during DST, usually 60
+    pub save_minutes: i16,
+    /// switch to DST, given in local standard time
+    pub start: Transition,
+    /// switch back, given in local daylight time
+    pub end: Transition,
+}
+
+/// A day and time of the year, `Mm.w.d/time` in a POSIX TZ string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    /// `1..=12`
+    pub month: u8,
+    /// `1..=5`, the first to fourth `weekday` of the month, 5 for the last
+    pub week: u8,
+    /// `0..=6`, Sunday is 0
+    pub weekday: u8,
+    /// local wall-clock time in minutes after midnight, `0..=24 * 60`
+    pub minutes: u16,
+}
+
+/// The part of a `TimeZone` that is out of range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeZoneError {
+    /// standard offset outside `-MAX_OFFSET_MINUTES..=MAX_OFFSET_MINUTES`
+    Offset(i16),
+    /// DST saving of zero, or a daylight offset out of range
+    Save(i16),
+    /// month, week, weekday or time of a transition out of range
+    Transition,
+    /// not a POSIX TZ string this module understands
+    Format,
+}
+
+impl fmt::Display for TimeZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZoneError::Offset(minutes) => write!(
+                f,
+                "UTC offset of {} minutes is outside +-{}",
+                minutes, MAX_OFFSET_MINUTES
+            ),
+            TimeZoneError::Save(minutes) => {
+                write!(f, "DST saving of {} minutes is out of range", minutes)
+            }
+            TimeZoneError::Transition => write!(f, "DST transition out of range"),
+            TimeZoneError::Format => write!(
+                f,
+                "expected a POSIX TZ string such as UTC0 or EET-2EEST,M3.5.0/3,M10.5.0/4"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TimeZoneError {}
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone {
+        utc_offset_minutes: 0,
+        dst: None,
+    };
+
+    pub const fn validate(&self) -> Result<(), TimeZoneError> {
+        let std = self.utc_offset_minutes;
+        if std < -MAX_OFFSET_MINUTES || std > MAX_OFFSET_MINUTES {
+            return Err(TimeZoneError::Offset(std));
+        }
+        if let Some(dst) = &self.dst {
+            let daylight = std as i32 + dst.save_minutes as i32;
+            if dst.save_minutes == 0
+                || daylight < -(MAX_OFFSET_MINUTES as i32)
+                || daylight > MAX_OFFSET_MINUTES as i32
+            {
+                return Err(TimeZoneError::Save(dst.save_minutes));
+            }
+            if !dst.start.is_valid() || !dst.end.is_valid() {
+                return Err(TimeZoneError::Transition);
+            }
+        }
+        Ok(())
+    }
+
+    /// Local time minus UTC in minutes at the instant `utc_millis`, for a
+    /// `TimeZone` that passed `validate`
+    pub fn offset_minutes_at(&self, utc_millis: i64) -> i16 {
+        let std = self.utc_offset_minutes;
+        let Some(dst) = &self.dst else {
+            return std;
+        };
+        let daylight = std + dst.save_minutes;
+        // the transitions of the year it is in local standard time
+        let local_days = (utc_millis + std as i64 * MILLIS_PER_MINUTE).div_euclid(MILLIS_PER_DAY);
+        let (year, _, _) = civil_from_days(local_days);
+        let start = dst.start.utc_millis(year, std);
+        let end = dst.end.utc_millis(year, daylight);
+        let in_dst = if start <= end {
+            start <= utc_millis && utc_millis < end
+        } else {
+            // southern hemisphere, DST over the turn of the year
+            utc_millis < end || start <= utc_millis
+        };
+        if in_dst {
+            daylight
+        } else {
+            std
+        }
+    }
+
+    /// Epoch milliseconds shifted to local time, `UtcDateTime::from_epoch_millis`
+    /// of the result has the local wall-clock fields
+    pub fn local_epoch_millis(&self, utc_millis: i64) -> i64 {
+        utc_millis + self.offset_minutes_at(utc_millis) as i64 * MILLIS_PER_MINUTE
+    }
+}
+
+impl Transition {
+    const fn is_valid(&self) -> bool {
+        self.month >= 1
+            && self.month <= 12
+            && self.week >= 1
+            && self.week <= 5
+            && self.weekday <= 6
+            && self.minutes <= 24 * 60
+    }
+
+    // the instant of the transition in `year`, at local offset `offset_minutes`
+    fn utc_millis(&self, year: i64, offset_minutes: i16) -> i64 {
+        let first = days_from_civil(year, self.month as i64, 1);
+        // 1970-01-01 was a Thursday
+        let first_weekday = (first + 4).rem_euclid(7);
+        let mut day =
+            1 + (self.weekday as i64 - first_weekday).rem_euclid(7) + 7 * (self.week as i64 - 1);
+        let last = days_in_month(year as i32, self.month as u32).unwrap_or(28) as i64;
+        if day > last {
+            day -= 7;
+        }
+        let local = (first + day - 1) * MILLIS_PER_DAY + self.minutes as i64 * MILLIS_PER_MINUTE;
+        local - offset_minutes as i64 * MILLIS_PER_MINUTE
+    }
+}
+
+/// POSIX TZ string with `M` rules, `UTC0`, `<+0530>-5:30` or
+/// `EET-2EEST,M3.5.0/3,M10.5.0/4`
+///
+/// POSIX counts offsets west of Greenwich positive, `TimeZone` east. Without
+/// an offset the daylight time is an hour ahead, without a time a transition
+/// is at 02:00. The `Jn` and `n` day forms and offsets in seconds are not
+/// taken.
+#[cfg(feature = "std")]
+impl FromStr for TimeZone {
+    type Err = TimeZoneError;
+
+    fn from_str(s: &str) -> Result<Self, TimeZoneError> {
+        let mut p = Parser(s.as_bytes());
+        p.name()?;
+        let std = -p.offset()?;
+        let tz = if p.0.is_empty() {
+            TimeZone {
+                utc_offset_minutes: std,
+                dst: None,
+            }
+        } else {
+            p.name()?;
+            let daylight = match p.0.first() {
+                Some(b',') => std + 60,
+                _ => -p.offset()?,
+            };
+            p.expect(b',')?;
+            let start = p.transition()?;
+            p.expect(b',')?;
+            let end = p.transition()?;
+            TimeZone {
+                utc_offset_minutes: std,
+                dst: Some(DstRule {
+                    save_minutes: daylight - std,
+                    start,
+                    end,
+                }),
+            }
+        };
+        if !p.0.is_empty() {
+            return Err(TimeZoneError::Format);
+        }
+        tz.validate()?;
+        Ok(tz)
+    }
+}
+
+// what is left of the TZ string being parsed
+#[cfg(feature = "std")]
+struct Parser<'a>(&'a [u8]);
+
+#[cfg(feature = "std")]
+impl Parser<'_> {
+    fn expect(&mut self, c: u8) -> Result<(), TimeZoneError> {
+        match self.0.split_first() {
+            Some((&first, rest)) if first == c => {
+                self.0 = rest;
+                Ok(())
+            }
+            _ => Err(TimeZoneError::Format),
+        }
+    }
+
+    // at least `min` characters matching `f`
+    fn take(&mut self, min: usize, f: impl Fn(&u8) -> bool) -> Result<&[u8], TimeZoneError> {
+        let n = self.0.iter().take_while(|c| f(c)).count();
+        if n < min {
+            return Err(TimeZoneError::Format);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    // `EET`, or quoted as `<+0530>`
+    fn name(&mut self) -> Result<(), TimeZoneError> {
+        if self.expect(b'<').is_ok() {
+            self.take(3, |c| c.is_ascii_alphanumeric() || *c == b'+' || *c == b'-')?;
+            self.expect(b'>')
+        } else {
+            self.take(3, u8::is_ascii_alphabetic).map(|_| ())
+        }
+    }
+
+    // up to `max_len` digits
+    fn number(&mut self, max_len: usize) -> Result<i16, TimeZoneError> {
+        let digits = self.take(1, u8::is_ascii_digit)?;
+        if digits.len() > max_len {
+            return Err(TimeZoneError::Format);
+        }
+        Ok(digits
+            .iter()
+            .fold(0, |value, c| value * 10 + (c - b'0') as i16))
+    }
+
+    // `hh[:mm]` in minutes, hours up to 24
+    fn time(&mut self) -> Result<i16, TimeZoneError> {
+        let hours = self.number(2)?;
+        let minutes = if self.expect(b':').is_ok() {
+            self.number(2)?
+        } else {
+            0
+        };
+        if hours > 24 || minutes > 59 {
+            return Err(TimeZoneError::Format);
+        }
+        Ok(hours * 60 + minutes)
+    }
+
+    // `[+-]hh[:mm]` in minutes, POSIX sign
+    fn offset(&mut self) -> Result<i16, TimeZoneError> {
+        let sign = match self.0.first() {
+            Some(b'-') => -1,
+            Some(b'+') => 1,
+            _ => return self.time(),
+        };
+        self.0 = &self.0[1..];
+        Ok(sign * self.time()?)
+    }
+
+    // `Mm.w.d[/time]`
+    fn transition(&mut self) -> Result<Transition, TimeZoneError> {
+        self.expect(b'M')?;
+        let month = self.number(2)?;
+        self.expect(b'.')?;
+        let week = self.number(1)?;
+        self.expect(b'.')?;
+        let weekday = self.number(1)?;
+        let minutes = if self.expect(b'/').is_ok() {
+            self.time()?
+        } else {
+            2 * 60
+        };
+        Ok(Transition {
+            month: month as u8,
+            week: week as u8,
+            weekday: weekday as u8,
+            minutes: minutes as u16,
+        })
+    }
+}
+
+// Finland, EET/EEST with the EU rule
+#[cfg(test)]
+const FINLAND: TimeZone = TimeZone {
+    utc_offset_minutes: 120,
+    dst: Some(DstRule {
+        save_minutes: 60,
+        start: Transition {
+            month: 3,
+            week: 5,
+            weekday: 0,
+            minutes: 3 * 60,
+        },
+        end: Transition {
+            month: 10,
+            week: 5,
+            weekday: 0,
+            minutes: 4 * 60,
+        },
+    }),
+};
+
+#[cfg(test)]
+fn millis(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> i64 {
+    crate::date_time::UtcDateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second: 0,
+        nanoseconds: 0,
+    }
+    .to_epoch_millis()
+    .unwrap()
+}
+
+#[test]
+fn finnish_dst_transitions() {
+    // 2026: last Sundays are March 29 and October 25, both changes at 01:00 UTC
+    let minute = MILLIS_PER_MINUTE;
+    let start = millis(2026, 3, 29, 1, 0);
+    assert_eq!(FINLAND.offset_minutes_at(start - 1), 120);
+    assert_eq!(FINLAND.offset_minutes_at(start), 180);
+    let end = millis(2026, 10, 25, 1, 0);
+    assert_eq!(FINLAND.offset_minutes_at(end - 1), 180);
+    assert_eq!(FINLAND.offset_minutes_at(end), 120);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2026, 1, 1, 0, 0)), 120);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2026, 7, 1, 12, 0)), 180);
+
+    // 03:00 local does not exist in spring, 03:00 to 04:00 happens twice in autumn
+    let local = |utc| {
+        crate::date_time::UtcDateTime::from_epoch_millis(FINLAND.local_epoch_millis(utc)).unwrap()
+    };
+    assert_eq!(
+        (local(start - minute).hour, local(start - minute).minute),
+        (2, 59)
+    );
+    assert_eq!((local(start).hour, local(start).minute), (4, 0));
+    assert_eq!(
+        (local(end - minute).hour, local(end - minute).minute),
+        (3, 59)
+    );
+    assert_eq!((local(end).hour, local(end).minute), (3, 0));
+
+    // a month with five Sundays and one with four, and a leap year
+    assert_eq!(FINLAND.offset_minutes_at(millis(2024, 3, 31, 0, 59)), 120);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2024, 3, 31, 1, 0)), 180);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2024, 10, 27, 0, 59)), 180);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2024, 10, 27, 1, 0)), 120);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2027, 3, 28, 1, 0)), 180);
+    assert_eq!(FINLAND.offset_minutes_at(millis(2027, 3, 21, 1, 0)), 120);
+}
+
+#[test]
+fn southern_dst_spans_new_year() {
+    // New Zealand, NZST-12NZDT,M9.5.0,M4.1.0/3
+    let nz = TimeZone {
+        utc_offset_minutes: 12 * 60,
+        dst: Some(DstRule {
+            save_minutes: 60,
+            start: Transition {
+                month: 9,
+                week: 5,
+                weekday: 0,
+                minutes: 2 * 60,
+            },
+            end: Transition {
+                month: 4,
+                week: 1,
+                weekday: 0,
+                minutes: 3 * 60,
+            },
+        }),
+    };
+    // 2026-04-05 03:00 NZDT and 2026-09-27 02:00 NZST
+    assert_eq!(nz.offset_minutes_at(millis(2026, 4, 4, 13, 59)), 13 * 60);
+    assert_eq!(nz.offset_minutes_at(millis(2026, 4, 4, 14, 0)), 12 * 60);
+    assert_eq!(nz.offset_minutes_at(millis(2026, 9, 26, 13, 59)), 12 * 60);
+    assert_eq!(nz.offset_minutes_at(millis(2026, 9, 26, 14, 0)), 13 * 60);
+    assert_eq!(nz.offset_minutes_at(millis(2026, 12, 31, 12, 0)), 13 * 60);
+    assert_eq!(nz.offset_minutes_at(millis(2027, 1, 1, 12, 0)), 13 * 60);
+}
+
+#[test]
+fn out_of_range_zones_are_rejected() {
+    assert_eq!(TimeZone::UTC.validate(), Ok(()));
+    assert_eq!(FINLAND.validate(), Ok(()));
+    let far = TimeZone {
+        utc_offset_minutes: 24 * 60,
+        dst: None,
+    };
+    assert_eq!(far.validate(), Err(TimeZoneError::Offset(24 * 60)));
+
+    let Some(rule) = FINLAND.dst else {
+        unreachable!()
+    };
+    let with = |dst| TimeZone {
+        dst: Some(dst),
+        ..FINLAND
+    };
+    let no_saving = DstRule {
+        save_minutes: 0,
+        ..rule
+    };
+    assert_eq!(with(no_saving).validate(), Err(TimeZoneError::Save(0)));
+    for start in [
+        Transition {
+            month: 13,
+            ..rule.start
+        },
+        Transition {
+            week: 0,
+            ..rule.start
+        },
+        Transition {
+            weekday: 7,
+            ..rule.start
+        },
+        Transition {
+            minutes: 24 * 60 + 1,
+            ..rule.start
+        },
+    ] {
+        let dst = DstRule { start, ..rule };
+        assert_eq!(with(dst).validate(), Err(TimeZoneError::Transition));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn posix_tz_strings() {
+    assert_eq!("UTC0".parse(), Ok(TimeZone::UTC));
+    assert_eq!("EET-2EEST,M3.5.0/3,M10.5.0/4".parse(), Ok(FINLAND));
+    assert_eq!("EET-2EEST-3,M3.5.0/3,M10.5.0/4".parse(), Ok(FINLAND));
+    assert_eq!(
+        "<+0530>-5:30".parse(),
+        Ok(TimeZone {
+            utc_offset_minutes: 330,
+            dst: None
+        })
+    );
+    assert_eq!(
+        "EST5EDT,M3.2.0,M11.1.0".parse(),
+        Ok(TimeZone {
+            utc_offset_minutes: -300,
+            dst: Some(DstRule {
+                save_minutes: 60,
+                start: Transition {
+                    month: 3,
+                    week: 2,
+                    weekday: 0,
+                    minutes: 120
+                },
+                end: Transition {
+                    month: 11,
+                    week: 1,
+                    weekday: 0,
+                    minutes: 120
+                },
+            }),
+        })
+    );
+
+    for bad in [
+        "",
+        "UTC",
+        "U0",
+        "EET-2EEST",
+        "EET-2EEST,M3.5.0/3",
+        "EET-2EEST,J60,J300",
+        "EET-2EEST,M3.5.0/3,M10.5.0/4,",
+        "EET-2:00:00",
+        "EET-25",
+    ] {
+        assert_eq!(
+            bad.parse::<TimeZone>(),
+            Err(TimeZoneError::Format),
+            "{}",
+            bad
+        );
+    }
+    assert_eq!(
+        "EET-2EEST,M13.5.0/3,M10.5.0/4".parse::<TimeZone>(),
+        Err(TimeZoneError::Transition)
+    );
+}
@@ -0,0 +1,48 @@
+//! Serialization backend of the frame payload
+//!
+//! `ssmarshal` (fixed width fields, the format of deployed firmware) unless
+//! the `postcard` feature is enabled, which switches to postcard's varint
+//! encoding. The backend is picked at compile time, so host and firmware must
+//! be built with the same choice. The CRC trailer is a fixed width little
+//! endian `u32` with either backend.
+
+use crate::Faults;
+
+/// Name of the active backend, for diagnostics
+#[cfg(not(feature = "postcard"))]
+pub const BACKEND: &str = "ssmarshal";
+#[cfg(feature = "postcard")]
+pub const BACKEND: &str = "postcard";
+
+/// Serialize `t` into `buf`, returns the number of bytes used
+pub fn serialize<T: serde::Serialize>(buf: &mut [u8], t: &T) -> Result<usize, Faults> {
+    #[cfg(not(feature = "postcard"))]
+    let n = ssmarshal::serialize(buf, t).map_err(|_| Faults::BufferTooSmall)?;
+    #[cfg(feature = "postcard")]
+    let n = postcard::to_slice(t, buf)
+        .map_err(|_| Faults::BufferTooSmall)?
+        .len();
+    Ok(n)
+}
+
+/// Deserialize a `T` from the start of `buf`, returns it with the number of bytes used
+pub fn deserialize<T>(buf: &[u8]) -> Result<(T, usize), Faults>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    #[cfg(not(feature = "postcard"))]
+    let res = ssmarshal::deserialize(buf)?;
+    #[cfg(feature = "postcard")]
+    let res = postcard::take_from_bytes(buf).map(|(t, rest)| (t, buf.len() - rest.len()))?;
+    Ok(res)
+}
+
+#[cfg(feature = "postcard")]
+impl From<postcard::Error> for Faults {
+    fn from(e: postcard::Error) -> Self {
+        match e {
+            postcard::Error::DeserializeUnexpectedEnd => Faults::Truncated,
+            _ => Faults::Deserialize,
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Property tests for the CRC/COBS codec
+//!
+//! Every `Command` and `Upstream` variant must survive a roundtrip through
+//! `serialize_crc_cobs`/`deserialize_crc_cobs`, and arbitrary input must only
+//! ever decode to a value or a `Faults`, never panic. The streaming encoder
+//! must produce the same bytes as the buffered one. No value may exceed the
+//! `MaxEncodedSize` bound of its type.
+
+use proptest::prelude::*;
+
+use shared::{
+    date_time::UtcDateTime,
+    deserialize_crc_cobs,
+    encoded_size::{max_payload_len, FrameBuf},
+    encoder::serialize_crc_cobs_to,
+    error::ErrorCode,
+    event::Event,
+    frame_accumulator::FrameAccumulator,
+    hello::HelloInfo,
+    serialize_crc_cobs,
+    time_zone::{DstRule, TimeZone, Transition},
+    wire, Action, Command, Faults, Message, Response, Seq, Upstream,
+};
+
+// Fails to compile when a variant is added, a reminder to extend the strategies below
+#[allow(dead_code)]
+fn all_variants_covered(
+    cmd: &Command,
+    rsp: &Response,
+    msg: &Message,
+    action: &Action,
+    code: &ErrorCode,
+    up: &Upstream,
+    event: &Event,
+) {
+    match cmd {
+        Command::Set(..)
+        | Command::Get(..)
+        | Command::Hello(..)
+        | Command::Action(..)
+        | Command::Batch(..) => {}
+    }
+    match rsp {
+        Response::Data(..)
+        | Response::SetOk
+        | Response::ParseError
+        | Response::NotOK
+        | Response::Illegal
+        | Response::Hello(..)
+        | Response::Error(..)
+        | Response::BatchRejected(..) => {}
+    }
+    match msg {
+        Message::A(..)
+        | Message::B(..)
+        | Message::C(..)
+        | Message::D(..)
+        | Message::E(..)
+        | Message::F(..)
+        | Message::G(..) => {}
+    }
+    match action {
+        Action::SetTime(..)
+        | Action::BlinkOff
+        | Action::BlinkNow(..)
+        | Action::BlinkAt(..)
+        | Action::SetRgb(..)
+        | Action::SetTimeEpoch(..)
+        | Action::BlinkAtEpoch(..)
+        | Action::SetTimeZone(..) => {}
+    }
+    match code {
+        ErrorCode::TimeNotSet
+        | ErrorCode::InvalidDate
+        | ErrorCode::FrequencyOutOfRange
+        | ErrorCode::DurationOutOfRange
+        | ErrorCode::UnknownCommand
+        | ErrorCode::CrcMismatch
+        | ErrorCode::BufferOverflow
+        | ErrorCode::AuthFailed
+        | ErrorCode::InvalidTimeZone => {}
+    }
+    match up {
+        Upstream::Response(..) | Upstream::Event(..) => {}
+    }
+    match event {
+        Event::BlinkStarted(..)
+        | Event::BlinkEnded(..)
+        | Event::TimeNotSet
+        | Event::Rebooted
+        | Event::RxOverflow(..) => {}
+    }
+}
+
+fn utc_date_time() -> impl Strategy<Value = UtcDateTime> {
+    (any::<i32>(), any::<[u32; 6]>()).prop_map(|(year, f)| UtcDateTime {
+        year,
+        month: f[0],
+        day: f[1],
+        hour: f[2],
+        minute: f[3],
+        second: f[4],
+        nanoseconds: f[5],
+    })
+}
+
+fn transition() -> impl Strategy<Value = Transition> {
+    any::<([u8; 3], u16)>().prop_map(|([month, week, weekday], minutes)| Transition {
+        month,
+        week,
+        weekday,
+        minutes,
+    })
+}
+
+fn time_zone() -> impl Strategy<Value = TimeZone> {
+    let dst =
+        (any::<i16>(), transition(), transition()).prop_map(|(save_minutes, start, end)| DstRule {
+            save_minutes,
+            start,
+            end,
+        });
+    (any::<i16>(), proptest::option::of(dst)).prop_map(|(utc_offset_minutes, dst)| TimeZone {
+        utc_offset_minutes,
+        dst,
+    })
+}
+
+fn message() -> impl Strategy<Value = Message> {
+    prop_oneof![
+        utc_date_time().prop_map(Message::A),
+        any::<u32>().prop_map(Message::B),
+        any::<(u32, u32)>().prop_map(|(d, f)| Message::C(d, f)),
+        (utc_date_time(), any::<u32>(), any::<u32>()).prop_map(|(t, d, f)| Message::D(t, d, f)),
+        any::<i64>().prop_map(Message::E),
+        any::<(i64, u32, u32)>().prop_map(|(t, d, f)| Message::F(t, d, f)),
+        time_zone().prop_map(Message::G),
+    ]
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    prop_oneof![
+        utc_date_time().prop_map(Action::SetTime),
+        Just(Action::BlinkOff),
+        any::<(u32, u32)>().prop_map(|(d, f)| Action::BlinkNow(d, f)),
+        (utc_date_time(), any::<u32>(), any::<u32>())
+            .prop_map(|(t, d, f)| Action::BlinkAt(t, d, f)),
+        any::<bool>().prop_map(Action::SetRgb),
+        any::<i64>().prop_map(Action::SetTimeEpoch),
+        any::<(i64, u32, u32)>().prop_map(|(t, d, f)| Action::BlinkAtEpoch(t, d, f)),
+        time_zone().prop_map(Action::SetTimeZone),
+    ]
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        (any::<u32>(), message(), any::<u32>()).prop_map(|(id, m, dev)| Command::Set(id, m, dev)),
+        any::<(u32, u32, u32)>().prop_map(|(id, p, dev)| Command::Get(id, p, dev)),
+        any::<u32>().prop_map(Command::Hello),
+        (action(), any::<u32>()).prop_map(|(a, dev)| Command::Action(a, dev)),
+        (
+            proptest::array::uniform4(proptest::option::of(action())),
+            any::<u32>()
+        )
+            .prop_map(|(batch, dev)| Command::Batch(batch, dev)),
+    ]
+}
+
+fn hello_info() -> impl Strategy<Value = HelloInfo> {
+    (
+        any::<u16>(),
+        any::<[u8; 16]>(),
+        any::<[u8; 8]>(),
+        any::<(u32, u32)>(),
+    )
+        .prop_map(
+            |(protocol_version, fw_version, git_hash, (dev_id, capabilities))| HelloInfo {
+                protocol_version,
+                fw_version,
+                git_hash,
+                dev_id,
+                capabilities,
+            },
+        )
+}
+
+fn error_code() -> impl Strategy<Value = ErrorCode> {
+    prop_oneof![
+        Just(ErrorCode::TimeNotSet),
+        Just(ErrorCode::InvalidDate),
+        Just(ErrorCode::FrequencyOutOfRange),
+        Just(ErrorCode::DurationOutOfRange),
+        Just(ErrorCode::UnknownCommand),
+        Just(ErrorCode::CrcMismatch),
+        Just(ErrorCode::BufferOverflow),
+        Just(ErrorCode::AuthFailed),
+        Just(ErrorCode::InvalidTimeZone),
+    ]
+}
+
+fn response() -> impl Strategy<Value = Response> {
+    prop_oneof![
+        (any::<(u32, u32)>(), any::<i64>(), any::<u32>())
+            .prop_map(|((id, p), v, dev)| Response::Data(id, p, v, dev)),
+        Just(Response::SetOk),
+        Just(Response::ParseError),
+        Just(Response::NotOK),
+        Just(Response::Illegal),
+        hello_info().prop_map(Response::Hello),
+        (error_code(), any::<u32>()).prop_map(|(code, detail)| Response::Error(code, detail)),
+        (any::<u8>(), error_code(), any::<u32>())
+            .prop_map(|(i, code, detail)| Response::BatchRejected(i, code, detail)),
+    ]
+}
+
+fn event() -> impl Strategy<Value = Event> {
+    prop_oneof![
+        any::<i64>().prop_map(Event::BlinkStarted),
+        any::<i64>().prop_map(Event::BlinkEnded),
+        Just(Event::TimeNotSet),
+        Just(Event::Rebooted),
+        any::<u32>().prop_map(Event::RxOverflow),
+    ]
+}
+
+fn upstream() -> impl Strategy<Value = Upstream> {
+    prop_oneof![
+        response().prop_map(Upstream::Response),
+        (event(), any::<u32>()).prop_map(|(e, dev)| Upstream::Event(e, dev)),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn command_roundtrip(seq in any::<Seq>(), cmd in command()) {
+        let mut buf = [0u8; 256];
+        let frame = serialize_crc_cobs(seq, &cmd, &mut buf).unwrap();
+        prop_assert_eq!(frame.last(), Some(&0));
+
+        let mut frame = frame.to_vec();
+        prop_assert_eq!(deserialize_crc_cobs::<Command>(&mut frame), Ok((seq, cmd)));
+    }
+
+    #[test]
+    fn upstream_roundtrip(seq in any::<Seq>(), up in upstream()) {
+        let mut buf = [0u8; 256];
+        let frame = serialize_crc_cobs(seq, &up, &mut buf).unwrap();
+
+        let mut frame = frame.to_vec();
+        prop_assert_eq!(deserialize_crc_cobs::<Upstream>(&mut frame), Ok((seq, up)));
+    }
+
+    #[test]
+    fn roundtrip_through_accumulator(seq in any::<Seq>(), cmd in command()) {
+        let mut buf = [0u8; 256];
+        let frame = serialize_crc_cobs(seq, &cmd, &mut buf).unwrap();
+
+        let mut acc = FrameAccumulator::<256>::new();
+        let mut decoded = None;
+        for &b in frame {
+            if let Some(f) = acc.push(b) {
+                decoded = Some(deserialize_crc_cobs::<Command>(f));
+            }
+        }
+        prop_assert_eq!(decoded, Some(Ok((seq, cmd))));
+    }
+
+    #[test]
+    fn streaming_matches_buffered(seq in any::<Seq>(), rsp in response()) {
+        let mut buf = [0u8; 256];
+        let frame = serialize_crc_cobs(seq, &rsp, &mut buf).unwrap();
+
+        let mut scratch = [0u8; 256];
+        let mut streamed = Vec::new();
+        let n = serialize_crc_cobs_to(seq, &rsp, &mut scratch, |b: &[u8]| {
+            streamed.extend_from_slice(b);
+            Ok::<(), ()>(())
+        })
+        .unwrap();
+        prop_assert_eq!(n, frame.len());
+        prop_assert_eq!(&streamed[..], frame);
+    }
+
+    #[test]
+    fn bounds_hold(seq in any::<Seq>(), cmd in command(), up in upstream()) {
+        let mut buf = [0u8; 256];
+        prop_assert!(wire::serialize(&mut buf, &(seq, &cmd)).unwrap() <= max_payload_len::<Command>());
+        prop_assert!(wire::serialize(&mut buf, &(seq, &up)).unwrap() <= max_payload_len::<Upstream>());
+
+        let mut cmd_buf = FrameBuf::<Command>::new();
+        let frame = cmd_buf.encode(seq, &cmd).unwrap();
+        prop_assert_eq!(frame, serialize_crc_cobs(seq, &cmd, &mut buf).unwrap());
+        let mut up_buf = FrameBuf::<Upstream>::new();
+        prop_assert!(up_buf.encode(seq, &up).is_ok());
+    }
+
+    #[test]
+    fn too_small_buffer_is_a_fault(seq in any::<Seq>(), cmd in command()) {
+        let mut buf = [0u8; 8];
+        prop_assert_eq!(serialize_crc_cobs(seq, &cmd, &mut buf).err(), Some(Faults::BufferTooSmall));
+    }
+
+    #[test]
+    fn single_bit_flip_is_detected(seq in any::<Seq>(), cmd in command(), bit in any::<proptest::sample::Index>()) {
+        let mut buf = [0u8; 256];
+        let mut frame = serialize_crc_cobs(seq, &cmd, &mut buf).unwrap().to_vec();
+
+        // flip any bit but the delimiter's
+        let bit = bit.index((frame.len() - 1) * 8);
+        frame[bit / 8] ^= 1 << (bit % 8);
+        prop_assert!(deserialize_crc_cobs::<Command>(&mut frame).is_err());
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic(mut bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+        let mut copy = bytes.clone();
+        let _ = deserialize_crc_cobs::<Command>(&mut bytes);
+        let _ = deserialize_crc_cobs::<Upstream>(&mut copy);
+    }
+}
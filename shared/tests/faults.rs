@@ -0,0 +1,66 @@
+//! `deserialize_crc_cobs` on malformed frames, each one must come back as
+//! the matching `Faults` variant instead of a panic
+
+use shared::{deserialize_crc_cobs, serialize_crc_cobs, Command, Faults, Message, CKSUM};
+
+fn valid_frame() -> Vec<u8> {
+    let mut buf = [0u8; 64];
+    let cmd = Command::Set(5, Message::B(1), 1);
+    serialize_crc_cobs(7, &cmd, &mut buf).unwrap().to_vec()
+}
+
+// COBS decode the frame, let `corrupt` loose on seq, payload and CRC, encode again
+fn reframe(corrupt: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut frame = valid_frame();
+    let n = corncobs::decode_in_place(&mut frame).unwrap();
+    let mut raw = frame[..n].to_vec();
+    corrupt(&mut raw);
+    let mut out = vec![0; corncobs::max_encoded_len(raw.len())];
+    let n = corncobs::encode_buf(&raw, &mut out);
+    out.truncate(n);
+    out
+}
+
+fn decode(mut frame: Vec<u8>) -> Result<(), Faults> {
+    deserialize_crc_cobs::<Command>(&mut frame).map(drop)
+}
+
+#[test]
+fn intact_frame_decodes() {
+    assert_eq!(decode(valid_frame()), Ok(()));
+}
+
+#[test]
+fn bad_crc_is_bit_flip() {
+    let frame = reframe(|raw| *raw.last_mut().unwrap() ^= 0x01);
+    assert_eq!(decode(frame), Err(Faults::BitFlipData));
+}
+
+#[test]
+fn truncated_payload_is_truncated() {
+    // the last two bytes of the payload lost, under a CRC that matches what is left
+    let frame = reframe(|raw| {
+        raw.truncate(raw.len() - 4 - 2);
+        let crc = CKSUM.checksum(raw);
+        raw.extend_from_slice(&crc.to_le_bytes());
+    });
+    assert_eq!(decode(frame), Err(Faults::Truncated));
+}
+
+#[test]
+fn frame_shorter_than_a_crc_is_truncated() {
+    let frame = reframe(|raw| raw.truncate(3));
+    assert_eq!(decode(frame), Err(Faults::Truncated));
+}
+
+#[test]
+fn garbage_runs_past_the_frame() {
+    // the code byte promises eight more bytes before the delimiter, there are three
+    assert_eq!(decode(vec![0x09, 0x11, 0x22, 0x33]), Err(Faults::Truncated));
+}
+
+#[test]
+fn garbage_payload_does_not_deserialize() {
+    let frame = reframe(|raw| raw.iter_mut().for_each(|b| *b = 0xff));
+    assert_eq!(decode(frame), Err(Faults::Deserialize));
+}
@@ -0,0 +1,249 @@
+//! Encoded frame size per `wire` backend
+//!
+//! Run with `cargo test --test frame_size -- --nocapture` to print the table,
+//! the active backend is marked with `*`.
+//!
+//! The `MaxEncodedSize` bounds must be reached exactly by the widest value of
+//! each type, anything less wastes buffer space on the device.
+
+use core::mem::size_of;
+
+use shared::{
+    batch,
+    date_time::UtcDateTime,
+    encoded_size::{max_frame_len, MaxEncodedSize},
+    error::ErrorCode,
+    event::Event,
+    get,
+    hello::{self, HelloInfo},
+    serialize_crc_cobs,
+    time_zone::{DstRule, TimeZone, Transition},
+    wire, Action, Command, Message, Response, Seq, Upstream, CKSUM,
+};
+
+// serialized `seq | payload`, then CRC and COBS the way `serialize_crc_cobs` does
+fn frame_len(payload: &[u8]) -> usize {
+    let mut raw = payload.to_vec();
+    raw.extend_from_slice(&CKSUM.checksum(payload).to_le_bytes());
+    let mut out = vec![0; corncobs::max_encoded_len(raw.len())];
+    corncobs::encode_buf(&raw, &mut out)
+}
+
+fn sizes<T: serde::Serialize>(seq: Seq, t: &T) -> (usize, usize) {
+    let mut buf = [0u8; 256];
+    let n = ssmarshal::serialize(&mut buf, &(seq, t)).unwrap();
+    let ssmarshal_len = frame_len(&buf[..n]);
+    let n = postcard::to_slice(&(seq, t), &mut buf).unwrap().len();
+    (ssmarshal_len, frame_len(&buf[..n]))
+}
+
+fn report<T: serde::Serialize + core::fmt::Debug>(t: &T) -> (usize, usize) {
+    let seq = 1234;
+    let (ssmarshal_len, postcard_len) = sizes(seq, t);
+
+    // the table must describe what serialize_crc_cobs actually sends
+    let mut buf = [0u8; 256];
+    let active = serialize_crc_cobs(seq, t, &mut buf).unwrap().len();
+    let (s, p) = match wire::BACKEND {
+        "postcard" => (" ", "*"),
+        _ => ("*", " "),
+    };
+    assert_eq!(
+        active,
+        if wire::BACKEND == "postcard" {
+            postcard_len
+        } else {
+            ssmarshal_len
+        }
+    );
+
+    println!("{ssmarshal_len:>4}{s} {postcard_len:>4}{p}  {t:?}");
+    (ssmarshal_len, postcard_len)
+}
+
+#[test]
+fn frame_sizes() {
+    let dt = UtcDateTime {
+        year: 2023,
+        month: 10,
+        day: 17,
+        hour: 14,
+        minute: 30,
+        second: 0,
+        nanoseconds: 0,
+    };
+    let info = HelloInfo {
+        protocol_version: hello::PROTOCOL_VERSION,
+        fw_version: hello::padded("0.1.0"),
+        git_hash: hello::padded("0123abcd"),
+        dev_id: 1,
+        capabilities: 0xff,
+    };
+
+    println!("ssmarshal postcard");
+    let commands = [
+        Command::Set(1, Message::A(dt), 1),
+        Command::Set(2, Message::B(0), 1),
+        Command::Set(3, Message::C(10, 3), 1),
+        Command::Set(4, Message::D(dt, 10, 3), 1),
+        Command::Set(1, Message::E(NANOS), 1),
+        Command::Set(4, Message::F(NANOS, 10, 3), 1),
+        Command::Get(get::TIME, 0, 1),
+        Command::Hello(1),
+        Command::Action(Action::SetTime(dt), 1),
+        Command::Action(Action::BlinkOff, 1),
+        Command::Action(Action::BlinkNow(10, 3), 1),
+        Command::Action(Action::BlinkAt(dt, 10, 3), 1),
+        Command::Action(Action::SetRgb(true), 1),
+        Command::Action(Action::SetTimeEpoch(NANOS), 1),
+        Command::Action(Action::BlinkAtEpoch(NANOS, 10, 3), 1),
+        Command::Action(Action::SetTimeZone(finland()), 1),
+        Command::Batch(
+            batch::from_actions(&[
+                Action::SetTime(dt),
+                Action::SetRgb(true),
+                Action::BlinkAt(dt, 10, 3),
+            ])
+            .unwrap(),
+            1,
+        ),
+    ];
+    for cmd in &commands {
+        report(cmd);
+    }
+
+    let responses = [
+        Response::Data(get::TIME, 0, 1_697_553_000_000, 1),
+        Response::SetOk,
+        Response::ParseError,
+        Response::NotOK,
+        Response::Illegal,
+        Response::Hello(info),
+        Response::Error(ErrorCode::FrequencyOutOfRange, 250),
+        Response::BatchRejected(2, ErrorCode::TimeNotSet, 0),
+    ];
+    for rsp in responses {
+        report(&Upstream::Response(rsp));
+    }
+
+    let events = [
+        Event::BlinkStarted(1_697_553_000_000),
+        Event::BlinkEnded(1_697_553_010_000),
+        Event::TimeNotSet,
+        Event::Rebooted,
+        Event::RxOverflow(80),
+    ];
+    for event in events {
+        report(&Upstream::Event(event, 1));
+    }
+
+    // the reason postcard is on offer at all
+    let (ssmarshal_len, postcard_len) = sizes(1, &Command::Set(4, Message::D(dt, 10, 3), 1));
+    assert!(postcard_len < ssmarshal_len / 2);
+
+    // and of the epoch forms with ssmarshal, postcard already packs the
+    // calendar fields of a present day date about as tightly
+    let calendar = sizes(1, &Command::Action(Action::BlinkAt(dt, 10, 3), 1));
+    let epoch = sizes(1, &Command::Action(Action::BlinkAtEpoch(NANOS, 10, 3), 1));
+    assert!(epoch.0 < calendar.0 * 2 / 3);
+    assert!(epoch.1 <= calendar.1 + 1);
+}
+
+// 2023-10-17T14:30:00Z, the `dt` of the table
+const NANOS: i64 = 1_697_553_000_000_000_000;
+
+// EET-2EEST,M3.5.0/3,M10.5.0/4
+fn finland() -> TimeZone {
+    TimeZone {
+        utc_offset_minutes: 120,
+        dst: Some(DstRule {
+            save_minutes: 60,
+            start: Transition {
+                month: 3,
+                week: 5,
+                weekday: 0,
+                minutes: 180,
+            },
+            end: Transition {
+                month: 10,
+                week: 5,
+                weekday: 0,
+                minutes: 240,
+            },
+        }),
+    }
+}
+
+// every integer at the end of its range that encodes widest
+fn widest_date_time() -> UtcDateTime {
+    UtcDateTime {
+        year: i32::MIN,
+        month: u32::MAX,
+        day: u32::MAX,
+        hour: u32::MAX,
+        minute: u32::MAX,
+        second: u32::MAX,
+        nanoseconds: u32::MAX,
+    }
+}
+
+fn assert_tight<T: serde::Serialize + MaxEncodedSize + core::fmt::Debug>(t: &T) {
+    let mut buf = [0u8; 256];
+    let n = wire::serialize(&mut buf, t).unwrap();
+    assert_eq!(n, T::MAX_ENCODED_SIZE, "{t:?}");
+}
+
+#[test]
+fn bounds_are_tight() {
+    let dt = widest_date_time();
+    let m = u32::MAX;
+    assert_tight(&dt);
+    assert_tight(&Message::D(dt, m, m));
+    assert_tight(&Action::BlinkAt(dt, m, m));
+    let widest = Some(Action::BlinkAt(dt, m, m));
+    assert_tight(&Command::Batch(
+        [widest.clone(), widest.clone(), widest.clone(), widest],
+        m,
+    ));
+    assert_tight(&Event::BlinkStarted(i64::MIN));
+    let widest = Transition {
+        month: u8::MAX,
+        week: u8::MAX,
+        weekday: u8::MAX,
+        minutes: u16::MAX,
+    };
+    assert_tight(&TimeZone {
+        utc_offset_minutes: i16::MIN,
+        dst: Some(DstRule {
+            save_minutes: i16::MIN,
+            start: widest,
+            end: widest,
+        }),
+    });
+
+    let info = HelloInfo {
+        protocol_version: u16::MAX,
+        fw_version: [0xff; hello::FW_VERSION_LEN],
+        git_hash: [0xff; hello::GIT_HASH_LEN],
+        dev_id: m,
+        capabilities: m,
+    };
+    assert_tight(&Response::Hello(info));
+    assert_tight(&Upstream::Response(Response::Hello(info)));
+
+    // the widest frame fits, COBS overhead included
+    let mut buf = [0u8; 256];
+    let cmd = Command::Batch(
+        batch::from_actions(&vec![Action::BlinkAt(dt, m, m); batch::BATCH_LEN]).unwrap(),
+        m,
+    );
+    let frame = serialize_crc_cobs(Seq::MAX, &cmd, &mut buf).unwrap();
+    assert!(frame.len() <= max_frame_len::<Command>());
+
+    println!(
+        "Command: at most {} bytes encoded, {} frame, size_of {} on this target",
+        Command::MAX_ENCODED_SIZE,
+        max_frame_len::<Command>(),
+        size_of::<Command>()
+    );
+}
@@ -0,0 +1,169 @@
+//! `TempoEstimator` against tap sequences in `SystemTimer` ticks at 16 MHz,
+//! generated with up to ±18 ms of jitter per press
+//!
+//! The `tap_tempo` examples print every press as `tap <ticks>`, sequences
+//! recorded from them on hardware can be added here the same way.
+
+use shared::tempo::{millis_to_ticks, ticks_to_millis, Tempo, TempoEstimator};
+
+// about 120 BPM
+const STEADY: [u64; 13] = [
+    51372904, 59270908, 67066281, 75155992, 82910347, 90928029, 98854426, 106597661, 114603109,
+    122337845, 130300399, 138056398, 145817154,
+];
+
+// 120 BPM, the sixth press bounced 55 ms after the fifth
+const DOUBLE_TAP: [u64; 13] = [
+    23901337, 31946512, 39887678, 48163233, 55899603, 64105130, 64981928, 72102415, 79899338,
+    87675700, 95566008, 103744584, 111561752,
+];
+
+// 120 BPM, one press missed after the sixth
+const MISSED_TAP: [u64; 13] = [
+    96004512, 103835446, 111939411, 119900873, 127792634, 135843892, 151891568, 159862960,
+    167748613, 175914774, 184026361, 191879153, 199921446,
+];
+
+// 120 BPM for eight intervals, then 90 BPM
+const SLOWING_DOWN: [u64; 17] = [
+    12440160, 20248888, 28159352, 36405447, 44356842, 52624953, 60383408, 68415395, 76583214,
+    87459694, 98030952, 108609799, 119279320, 130139661, 140537539, 150948600, 161476627,
+];
+
+// 120 BPM, a bounce 200 ms after the fifth press
+const BOUNCE_MID_BEAT: [u64; 13] = [
+    40118226, 48089281, 56120870, 64361957, 72342583, 80349731, 83553024, 88354704, 96174265,
+    104177467, 112253156, 120421155, 128191273,
+];
+
+// 120 BPM, bounces after the fourth and the fifth press
+const TWO_BOUNCES: [u64; 15] = [
+    7730915, 15713457, 23803765, 31898628, 34775957, 39688876, 43372297, 47408274, 55332613,
+    63203184, 71384651, 79492067, 87547117, 95580840, 103675387,
+];
+
+// 120 BPM for eight intervals, then 240 BPM
+const SPEEDING_UP: [u64; 21] = [
+    63500084, 71786758, 79980348, 88102423, 95997550, 103840047, 111717293, 119466454, 127618384,
+    131588423, 135684363, 139655575, 143786850, 147889084, 151748799, 155662163, 159777423,
+    163766797, 167906931, 171874216, 175747340,
+];
+
+fn estimates(taps: &[u64]) -> Vec<Option<Tempo>> {
+    let mut estimator: TempoEstimator = TempoEstimator::default();
+    taps.iter().map(|&ticks| estimator.tap(ticks)).collect()
+}
+
+fn assert_bpm(tempo: Option<Tempo>, low: f32, high: f32) {
+    let bpm = tempo.expect("an estimate").bpm();
+    assert!(
+        (low..=high).contains(&bpm),
+        "{bpm} BPM not in {low}..={high}"
+    );
+}
+
+#[test]
+fn ticks_convert_exactly() {
+    assert_eq!(ticks_to_millis(16_000), 1);
+    // the examples used to shift by 14, 2.4 % short
+    assert_eq!(ticks_to_millis(16_384), 1);
+    assert_eq!(ticks_to_millis(8_000_000), 500);
+    assert_eq!(millis_to_ticks(500), 8_000_000);
+
+    let tempo = Tempo {
+        period_ticks: 8_000_000,
+        confidence: 100,
+    };
+    assert_eq!(tempo.period_millis(), 500);
+    assert_eq!(tempo.bpm(), 120.0);
+}
+
+#[test]
+fn steady_taps() {
+    let estimates = estimates(&STEADY);
+    assert_eq!(estimates[0], None);
+    for tempo in &estimates[1..] {
+        assert_bpm(*tempo, 117.0, 124.0);
+    }
+
+    // confidence grows as the history fills up
+    let confidence: Vec<u8> = estimates[1..]
+        .iter()
+        .map(|t| t.unwrap().confidence)
+        .collect();
+    assert!(confidence[0] < confidence[7]);
+    assert!(confidence[11] >= 80, "{confidence:?}");
+}
+
+#[test]
+fn double_tap_is_rejected() {
+    let estimates = estimates(&DOUBLE_TAP);
+    for tempo in &estimates[1..] {
+        assert_bpm(*tempo, 117.0, 124.0);
+    }
+}
+
+#[test]
+fn bounce_keeps_the_real_press_as_reference() {
+    let estimates = estimates(&BOUNCE_MID_BEAT);
+    for tempo in &estimates[1..] {
+        assert_bpm(*tempo, 117.0, 124.0);
+    }
+    // the press after the bounce adds a whole interval, not what is left of it
+    assert!(estimates[7].unwrap().confidence > estimates[5].unwrap().confidence);
+}
+
+#[test]
+fn bounces_in_a_row_keep_the_estimate() {
+    let estimates = estimates(&TWO_BOUNCES);
+    for tempo in &estimates[1..] {
+        assert_bpm(*tempo, 117.0, 124.0);
+    }
+    assert!(estimates[14].unwrap().confidence >= 80);
+}
+
+#[test]
+fn missed_tap_is_rejected() {
+    let estimates = estimates(&MISSED_TAP);
+    for tempo in &estimates[1..] {
+        assert_bpm(*tempo, 117.0, 124.0);
+    }
+}
+
+#[test]
+fn follows_a_change_of_tempo() {
+    let estimates = estimates(&SLOWING_DOWN);
+    assert_bpm(estimates[8], 117.0, 124.0);
+    // a couple of taps are rejected before the new tempo is believed
+    assert_bpm(estimates[9], 117.0, 124.0);
+    assert_bpm(estimates[16], 88.0, 92.0);
+    // only just restarted, the history is not full
+    assert!(estimates[16].unwrap().confidence < estimates[8].unwrap().confidence);
+}
+
+#[test]
+fn follows_double_speed() {
+    // every other press looks like a bounce at first
+    let estimates = estimates(&SPEEDING_UP);
+    assert_bpm(estimates[8], 117.0, 124.0);
+    assert_bpm(estimates[20], 235.0, 245.0);
+}
+
+#[test]
+fn pause_starts_over() {
+    let mut estimator: TempoEstimator = TempoEstimator::default();
+    for ticks in STEADY {
+        estimator.tap(ticks);
+    }
+    assert!(estimator.tempo().is_some());
+
+    let later = STEADY[12] + millis_to_ticks(3000);
+    assert_eq!(estimator.tap(later), None);
+    assert_eq!(estimator.tempo(), None);
+
+    // 100 BPM from there
+    assert_bpm(estimator.tap(later + millis_to_ticks(600)), 99.0, 101.0);
+
+    // so does a timestamp going backwards
+    assert_eq!(estimator.tap(later), None);
+}